@@ -1,10 +1,15 @@
 use solana_client::rpc_client::RpcClient;
-use solana_sdk::{pubkey::Pubkey, commitment_config::CommitmentConfig};
-use anyhow::Result;
+use solana_sdk::{pubkey::Pubkey, signature::Signature, commitment_config::CommitmentConfig};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::{system_program, transaction::Transaction};
+use sha2::{Digest, Sha256};
+use anyhow::{anyhow, Result};
 use tracing::{info, warn};
 use std::str::FromStr;
 
-use crate::storage::{Storage, Manifest};
+use crate::manifest::Manifest;
+use crate::storage::{Attestation, Storage};
 
 pub struct Anchor {
     client: RpcClient,
@@ -18,13 +23,78 @@ impl Anchor {
         Ok(Self { client, program_id })
     }
 
+    /// The account for `cid` under `creator`, e.g. `kind` "manifest". CIDs
+    /// exceed Solana's 32-byte seed limit, so the program seeds with their
+    /// SHA-256 instead.
+    fn pda(&self, kind: &str, creator: &Pubkey, cid: &str) -> Result<Pubkey> {
+        let cid_hash = Sha256::digest(cid.as_bytes());
+        Pubkey::try_find_program_address(&[kind.as_bytes(), creator.as_ref(), &cid_hash], &self.program_id)
+            .map(|(pda, _)| pda)
+            .ok_or_else(|| anyhow!("no {} address for {} under {}", kind, cid, creator))
+    }
+
+    /// Creates the manifest PDA for `cid` under `creator`, which pays for and
+    /// signs the transaction. Returns the transaction signature.
+    pub async fn anchor_manifest(&self, cid: &str, creator: &Keypair) -> Result<Signature> {
+        let creator_pubkey = creator.pubkey();
+        let manifest_pda = self.pda("manifest", &creator_pubkey, cid)?;
+        let instruction = Instruction::new_with_bytes(
+            self.program_id,
+            &instruction_data("anchor_manifest", cid),
+            vec![
+                AccountMeta::new(manifest_pda, false),
+                AccountMeta::new(creator_pubkey, true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        let blockhash = self.client.get_latest_blockhash()?;
+        let transaction =
+            Transaction::new_signed_with_payer(&[instruction], Some(&creator_pubkey), &[creator], blockhash);
+        let signature = self.client.send_and_confirm_transaction(&transaction)?;
+        info!("Anchored manifest {} in tx {}", cid, signature);
+        Ok(signature)
+    }
+
+    /// Creates the retraction PDA for `cid` under `creator`. The program only
+    /// accepts it next to the creator's own manifest PDA, so an existing
+    /// retraction account proves the creator withdrew the manifest.
+    pub async fn anchor_retraction(&self, cid: &str, creator: &Keypair) -> Result<Signature> {
+        let creator_pubkey = creator.pubkey();
+        let manifest_pda = self.pda("manifest", &creator_pubkey, cid)?;
+        let retraction_pda = self.pda("retraction", &creator_pubkey, cid)?;
+        let instruction = Instruction::new_with_bytes(
+            self.program_id,
+            &instruction_data("retract_manifest", cid),
+            vec![
+                AccountMeta::new(retraction_pda, false),
+                AccountMeta::new_readonly(manifest_pda, false),
+                AccountMeta::new(creator_pubkey, true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        let blockhash = self.client.get_latest_blockhash()?;
+        let transaction =
+            Transaction::new_signed_with_payer(&[instruction], Some(&creator_pubkey), &[creator], blockhash);
+        let signature = self.client.send_and_confirm_transaction(&transaction)?;
+        info!("Anchored retraction of {} in tx {}", cid, signature);
+        Ok(signature)
+    }
+
+    /// Whether `creator` has retracted `cid` on-chain. RPC failures are
+    /// errors, not a "no".
+    pub async fn verify_retraction(&self, cid: &str, creator: &str) -> Result<bool> {
+        let creator_pubkey = Pubkey::from_str(creator)?;
+        let retraction_pda = self.pda("retraction", &creator_pubkey, cid)?;
+        let account = self
+            .client
+            .get_account_with_commitment(&retraction_pda, self.client.commitment())?
+            .value;
+        Ok(account.is_some())
+    }
+
     pub async fn verify_manifest(&self, cid: &str, creator: &str) -> Result<bool> {
-        // Derive manifest PDA
         let creator_pubkey = Pubkey::from_str(creator)?;
-        let (manifest_pda, _) = Pubkey::find_program_address(
-            &[b"manifest", creator_pubkey.as_ref(), cid.as_bytes()],
-            &self.program_id,
-        );
+        let manifest_pda = self.pda("manifest", &creator_pubkey, cid)?;
 
         // Fetch account info
         match self.client.get_account(&manifest_pda) {
@@ -43,21 +113,20 @@ impl Anchor {
 
     pub async fn get_manifest_provenance(&self, cid: &str, creator: &str) -> Result<Option<ManifestProvenance>> {
         let creator_pubkey = Pubkey::from_str(creator)?;
-        let (manifest_pda, _) = Pubkey::find_program_address(
-            &[b"manifest", creator_pubkey.as_ref(), cid.as_bytes()],
-            &self.program_id,
-        );
+        let manifest_pda = self.pda("manifest", &creator_pubkey, cid)?;
 
         match self.client.get_account(&manifest_pda) {
             Ok(account) => {
                 // Deserialize account data
                 // This would need proper deserialization based on the account struct
                 // For now, return mock data
+                let slot = 12345;
                 Ok(Some(ManifestProvenance {
                     finalized: true,
                     attestation_count: 3,
                     tx_signature: "mock_sig".to_string(),
-                    slot: 12345,
+                    slot,
+                    anchored_at: self.client.get_block_time(slot).ok(),
                 }))
             }
             Err(_) => Ok(None),
@@ -65,10 +134,80 @@ impl Anchor {
     }
 }
 
+/// Instruction data for a program method taking `cid: String`, such as
+/// `anchor_manifest`: the 8-byte Anchor discriminator followed by the
+/// Borsh-encoded CID.
+fn instruction_data(method: &str, cid: &str) -> Vec<u8> {
+    let mut data = Sha256::digest(format!("global:{}", method).as_bytes())[..8].to_vec();
+    data.extend_from_slice(&(cid.len() as u32).to_le_bytes());
+    data.extend_from_slice(cid.as_bytes());
+    data
+}
+
+/// Checks that an attestation was signed by the validator key it names.
+/// Confidences outside 0..=1 (or NaN) are refused before the signature is
+/// looked at.
+pub fn verify_attestation(attestation: &Attestation) -> Result<bool> {
+    if !(0.0..=1.0).contains(&attestation.confidence) {
+        return Err(anyhow!(
+            "attestation for {} has confidence {} outside 0..=1",
+            attestation.manifest_cid,
+            attestation.confidence
+        ));
+    }
+    let validator = Pubkey::from_str(&attestation.validator)?;
+    let signature = Signature::from_str(&attestation.signature)?;
+    Ok(signature.verify(validator.as_ref(), &attestation.signing_message()))
+}
+
 #[derive(Debug)]
 pub struct ManifestProvenance {
     pub finalized: bool,
     pub attestation_count: u64,
     pub tx_signature: String,
     pub slot: u64,
-}
\ No newline at end of file
+    /// Block time of the anchoring slot, if the RPC node still has it.
+    pub anchored_at: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    #[test]
+    fn test_verify_attestation() -> Result<()> {
+        let validator = Keypair::new();
+        let mut attestation = Attestation {
+            manifest_cid: "test_cid".to_string(),
+            validator: validator.pubkey().to_string(),
+            confidence: 0.75,
+            timestamp: 100,
+            signature: String::new(),
+        };
+        attestation.signature = validator.sign_message(&attestation.signing_message()).to_string();
+        assert!(verify_attestation(&attestation)?);
+
+        attestation.confidence = 1.0;
+        assert!(!verify_attestation(&attestation)?);
+
+        // Out-of-range scores are refused even when properly signed
+        for confidence in [1.5, -0.1, f64::NAN] {
+            attestation.confidence = confidence;
+            attestation.signature = validator.sign_message(&attestation.signing_message()).to_string();
+            assert!(verify_attestation(&attestation).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_pda_for_cidv0() -> Result<()> {
+        // 46 bytes: too long to be a seed itself
+        let cid = "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n";
+        let anchor = Anchor::new("http://127.0.0.1:1", "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS")?;
+        let creator = Keypair::new().pubkey();
+        assert_eq!(anchor.pda("manifest", &creator, cid)?, anchor.pda("manifest", &creator, cid)?);
+        assert_ne!(anchor.pda("manifest", &creator, cid)?, anchor.pda("manifest", &Keypair::new().pubkey(), cid)?);
+        Ok(())
+    }
+}
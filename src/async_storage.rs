@@ -0,0 +1,183 @@
+use std::path::Path;
+use std::thread;
+use anyhow::Result;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+use crate::manifest::Manifest;
+use crate::retraction::Retraction;
+use crate::storage::{
+    Attestation, CachedProvenance, ManifestQuery, ManifestRejection, MergeOutcome, Provenance, Storage,
+    SyncState, Tombstone,
+};
+use crate::store::{CatalogStore, MemoryStore};
+
+type Job = Box<dyn FnOnce(&dyn CatalogStore) + Send>;
+
+/// Async front end to a `CatalogStore`.
+///
+/// `rusqlite::Connection` is not `Sync` and every query blocks, so the
+/// store lives on a dedicated OS thread that runs jobs sent over a
+/// channel. Handles are cheap to clone and safe to share across tasks; the
+/// worker exits once the last handle is dropped.
+#[derive(Clone)]
+pub struct AsyncStorage {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+impl AsyncStorage {
+    /// Opens the SQLite catalog at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::spawn(Storage::new(path)?))
+    }
+
+    /// An empty catalog that lives only as long as the process.
+    pub fn in_memory() -> Self {
+        Self::spawn(MemoryStore::new())
+    }
+
+    /// Moves an open store onto a new worker thread.
+    pub fn spawn<S: CatalogStore + 'static>(store: S) -> Self {
+        let (jobs, mut rx) = mpsc::unbounded_channel::<Job>();
+        thread::Builder::new()
+            .name("catalog-db".to_string())
+            .spawn(move || {
+                while let Some(job) = rx.blocking_recv() {
+                    job(&store);
+                }
+                info!("Catalog DB worker stopped");
+            })
+            .expect("failed to spawn catalog DB worker");
+        Self { jobs }
+    }
+
+    /// Runs `f` on the worker thread and waits for its result without
+    /// blocking the async runtime.
+    pub async fn call<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&dyn CatalogStore) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |store| {
+            if tx.send(f(store)).is_err() {
+                warn!("Catalog DB caller went away before the result was ready");
+            }
+        });
+        self.jobs
+            .send(job)
+            .map_err(|_| anyhow::anyhow!("catalog DB worker has stopped"))?;
+        Ok(rx.await??)
+    }
+
+    pub async fn get_manifest(&self, cid: &str) -> Result<Option<Manifest>> {
+        let cid = cid.to_string();
+        self.call(move |s| s.get_manifest(&cid)).await
+    }
+
+    pub async fn list_manifests(&self) -> Result<Vec<Manifest>> {
+        self.call(|s| s.list_manifests()).await
+    }
+
+    pub async fn query_manifests(&self, query: &ManifestQuery) -> Result<Vec<Manifest>> {
+        let query = query.clone();
+        self.call(move |s| s.query_manifests(&query)).await
+    }
+
+    pub async fn count_manifests(&self, query: &ManifestQuery) -> Result<u64> {
+        let query = query.clone();
+        self.call(move |s| s.count_manifests(&query)).await
+    }
+
+    pub async fn merge_manifest(&self, manifest: &Manifest, source: &str, anchored_at: Option<i64>) -> Result<MergeOutcome> {
+        let manifest = manifest.clone();
+        let source = source.to_string();
+        self.call(move |s| s.merge_manifest(&manifest, &source, anchored_at)).await
+    }
+
+    pub async fn record_rejection(&self, rejection: &ManifestRejection) -> Result<()> {
+        let rejection = rejection.clone();
+        self.call(move |s| s.record_rejection(&rejection)).await
+    }
+
+    pub async fn get_tombstone(&self, cid: &str) -> Result<Option<Tombstone>> {
+        let cid = cid.to_string();
+        self.call(move |s| s.get_tombstone(&cid)).await
+    }
+
+    pub async fn apply_tombstone(&self, tombstone: &Tombstone) -> Result<()> {
+        let tombstone = tombstone.clone();
+        self.call(move |s| s.apply_tombstone(&tombstone)).await
+    }
+
+    pub async fn get_retractions(&self, cid: &str) -> Result<Vec<Retraction>> {
+        let cid = cid.to_string();
+        self.call(move |s| s.get_retractions(&cid)).await
+    }
+
+    pub async fn list_signed_retractions(&self, query: &ManifestQuery) -> Result<Vec<Retraction>> {
+        let query = query.clone();
+        self.call(move |s| s.list_signed_retractions(&query)).await
+    }
+
+    pub async fn cache_provenance(&self, cid: &str, provenance: Provenance) -> Result<()> {
+        let cid = cid.to_string();
+        self.call(move |s| s.cache_provenance(&cid, &provenance)).await
+    }
+
+    pub async fn get_cached_provenance(&self, cid: &str) -> Result<Option<CachedProvenance>> {
+        let cid = cid.to_string();
+        self.call(move |s| s.get_cached_provenance(&cid)).await
+    }
+
+    pub async fn invalidate_provenance(&self, cid: &str) -> Result<bool> {
+        let cid = cid.to_string();
+        self.call(move |s| s.invalidate_provenance(&cid)).await
+    }
+
+    pub async fn insert_attestation(&self, attestation: &Attestation) -> Result<()> {
+        let attestation = attestation.clone();
+        self.call(move |s| s.insert_attestation(&attestation)).await
+    }
+
+    pub async fn get_attestations(&self, cid: &str) -> Result<Vec<Attestation>> {
+        let cid = cid.to_string();
+        self.call(move |s| s.get_attestations(&cid)).await
+    }
+
+    pub async fn save_sync_state(&self, state: &SyncState) -> Result<()> {
+        let state = state.clone();
+        self.call(move |s| s.save_sync_state(&state)).await
+    }
+
+    pub async fn list_sync_state(&self) -> Result<Vec<SyncState>> {
+        self.call(|s| s.list_sync_state()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_calls() -> Result<()> {
+        let storage = AsyncStorage::spawn(Storage::in_memory()?);
+        let mut tasks = Vec::new();
+        for i in 0..8 {
+            let storage = storage.clone();
+            tasks.push(tokio::spawn(async move {
+                let manifest = Manifest {
+                    cid: format!("cid_{}", i),
+                    data: vec![i as u8],
+                    timestamp: i,
+                };
+                storage.merge_manifest(&manifest, "test", None).await
+            }));
+        }
+        for task in tasks {
+            assert_eq!(task.await??, MergeOutcome::Inserted);
+        }
+        assert_eq!(storage.list_manifests().await?.len(), 8);
+        Ok(())
+    }
+}
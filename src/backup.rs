@@ -0,0 +1,225 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{anyhow, bail, Result};
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+use tracing::{error, info};
+
+use crate::blobs::BlobStore;
+use crate::config::BackupConfig;
+use crate::migrations;
+
+/// Pages copied per backup step. Between steps the source is unlocked, so
+/// the daemon keeps writing while a backup runs.
+const PAGES_PER_STEP: i32 = 256;
+const STEP_PAUSE: Duration = Duration::from_millis(10);
+
+/// Prefix of scheduled backup files, which rotation relies on.
+const SCHEDULED_PREFIX: &str = "catalog-";
+
+#[derive(Debug, Default)]
+pub struct BackupReport {
+    pub schema_version: u32,
+    pub blobs_copied: usize,
+}
+
+/// Copies a live catalog to `dest` with SQLite's online backup API, then
+/// copies the blobs the snapshot references to `dest`'s blob directory.
+/// Safe to run while the daemon has the catalog open. Fails, leaving
+/// nothing at `dest`, if a blob was pruned before it could be copied, so a
+/// backup that succeeds can always be restored.
+pub fn backup(catalog: &Path, dest: &Path) -> Result<BackupReport> {
+    if dest.exists() {
+        bail!("{} already exists", dest.display());
+    }
+    let result = copy(catalog, dest);
+    if result.is_err() {
+        let _ = fs::remove_file(dest);
+        let _ = fs::remove_dir_all(dest.with_extension("blobs"));
+    }
+    result
+}
+
+fn copy(catalog: &Path, dest: &Path) -> Result<BackupReport> {
+    let src = Connection::open_with_flags(catalog, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut dst = Connection::open(dest)?;
+    Backup::new(&src, &mut dst)?.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None)?;
+
+    let mut report = BackupReport {
+        schema_version: migrations::current_version(&dst)?,
+        ..Default::default()
+    };
+    // Blobs are immutable, so copying after the snapshot is consistent
+    // unless one was pruned in between
+    let source_blobs = catalog.with_extension("blobs");
+    if source_blobs.exists() {
+        let source_blobs = BlobStore::new(source_blobs)?;
+        let dest_blobs = BlobStore::new(dest.with_extension("blobs"))?;
+        for key in referenced_blobs(&dst)? {
+            let data = source_blobs
+                .get(&key)?
+                .ok_or_else(|| anyhow!("blob {} was pruned before it could be backed up; try again", key))?;
+            dest_blobs.put(&data)?;
+            report.blobs_copied += 1;
+        }
+    }
+    info!("Backed up {} to {} (schema v{})", catalog.display(), dest.display(), report.schema_version);
+    Ok(report)
+}
+
+/// Checks that `path` is an intact catalog this binary can open. Returns its
+/// schema version.
+pub fn validate(path: &Path) -> Result<u32> {
+    if !path.exists() {
+        bail!("{} does not exist", path.display());
+    }
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        bail!("integrity check of {} failed: {}", path.display(), integrity);
+    }
+    let version = migrations::current_version(&conn)?;
+    if version > migrations::latest_version() {
+        bail!(
+            "{} has schema version {}, newer than supported version {}",
+            path.display(),
+            version,
+            migrations::latest_version()
+        );
+    }
+    Ok(version)
+}
+
+/// Replaces `catalog` with the backup at `source` after validating it. The
+/// daemon must be stopped. Older schemas are migrated on the next open.
+pub fn restore(source: &Path, catalog: &Path) -> Result<u32> {
+    let version = validate(source)?;
+    let src = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut dst = Connection::open(catalog)?;
+    Backup::new(&src, &mut dst)?.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None)?;
+
+    let source_blobs = source.with_extension("blobs");
+    if source_blobs.exists() {
+        let source_blobs = BlobStore::new(source_blobs)?;
+        let dest_blobs = BlobStore::new(catalog.with_extension("blobs"))?;
+        for key in referenced_blobs(&dst)? {
+            let data = source_blobs
+                .get(&key)?
+                .ok_or_else(|| anyhow!("backup is missing blob {}", key))?;
+            dest_blobs.put(&data)?;
+        }
+    }
+    info!("Restored {} from {} (schema v{})", catalog.display(), source.display(), version);
+    Ok(version)
+}
+
+/// Takes a timestamped backup into `config.dir` and deletes the oldest
+/// scheduled backups beyond `config.keep`.
+pub fn run_scheduled(catalog: &Path, config: &BackupConfig) -> Result<PathBuf> {
+    fs::create_dir_all(&config.dir)?;
+    let name = format!("{}{}.db", SCHEDULED_PREFIX, chrono::Utc::now().format("%Y%m%dT%H%M%SZ"));
+    let dest = Path::new(&config.dir).join(name);
+    backup(catalog, &dest)?;
+    let removed = rotate(Path::new(&config.dir), config.keep)?;
+    if removed > 0 {
+        info!("Rotated out {} old backups", removed);
+    }
+    Ok(dest)
+}
+
+/// Starts the periodic backup task for the daemon.
+pub fn spawn_scheduled(catalog: PathBuf, config: BackupConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval_hours.max(1) * 3600));
+        // The first tick fires immediately; skip it so startup isn't slowed
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let (catalog, config) = (catalog.clone(), config.clone());
+            match tokio::task::spawn_blocking(move || run_scheduled(&catalog, &config)).await {
+                Ok(Ok(path)) => info!("Scheduled backup written to {}", path.display()),
+                Ok(Err(e)) => error!("Scheduled backup failed: {}", e),
+                Err(e) => error!("Scheduled backup task panicked: {}", e),
+            }
+        }
+    });
+}
+
+/// Keeps the newest `keep` scheduled backups in `dir`. Returns how many
+/// were removed. Timestamped names sort chronologically.
+fn rotate(dir: &Path, keep: usize) -> Result<usize> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            name.starts_with(SCHEDULED_PREFIX) && name.ends_with(".db")
+        })
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for path in &backups[..excess] {
+        fs::remove_file(path)?;
+        let blobs = path.with_extension("blobs");
+        if blobs.exists() {
+            fs::remove_dir_all(blobs)?;
+        }
+    }
+    Ok(excess)
+}
+
+fn referenced_blobs(conn: &Connection) -> Result<Vec<String>> {
+    // Catalogs before schema v7 have no blob_key column and keep data inline
+    if migrations::current_version(conn)? < 7 {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare("SELECT DISTINCT blob_key FROM manifests WHERE blob_key IS NOT NULL AND data IS NULL")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use crate::storage::Storage;
+    use crate::store::CatalogStore;
+
+    #[test]
+    fn test_backup_and_restore() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nsd-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let catalog = dir.join("catalog.db");
+        let storage = Storage::new(&catalog)?;
+        let data = b"payload".to_vec();
+        let cid = crate::ipfs::compute_cid(&data);
+        storage.insert_manifest(&Manifest { cid: cid.clone(), data: data.clone(), timestamp: 1 })?;
+
+        // Back up while the catalog is still open
+        let snapshot = dir.join("snapshot.db");
+        let report = backup(&catalog, &snapshot)?;
+        assert_eq!(report.blobs_copied, 1);
+        assert_eq!(validate(&snapshot)?, migrations::latest_version());
+
+        // A snapshot whose blob is gone is refused and cleaned up
+        let shard = fs::read_dir(catalog.with_extension("blobs"))?.next().unwrap()?.path();
+        let moved = dir.join("moved");
+        fs::rename(&shard, &moved)?;
+        let broken = dir.join("broken.db");
+        assert!(backup(&catalog, &broken).is_err());
+        assert!(!broken.exists() && !broken.with_extension("blobs").exists());
+        fs::rename(&moved, &shard)?;
+
+        storage.prune_old(10)?;
+        drop(storage);
+        restore(&snapshot, &catalog)?;
+        assert_eq!(Storage::new(&catalog)?.get_manifest(&cid)?.unwrap().data, data);
+
+        // Garbage is refused before anything is overwritten
+        let bogus = dir.join("bogus.db");
+        fs::write(&bogus, b"not a database")?;
+        assert!(restore(&bogus, &catalog).is_err());
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use anyhow::{anyhow, Result};
+use tracing::{info, warn};
+
+use crate::ipfs;
+
+/// Distinguishes temp files of concurrent writers within this process.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Content-addressed payload store on disk.
+///
+/// Blobs are keyed by the CID of their bytes and sharded flatfs-style by the
+/// two characters before the last one (`<root>/<shard>/<key>`), since every
+/// CIDv0 shares the same `Qm` prefix. Identical content maps to the same file,
+/// so repeated arrivals are stored once.
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self> {
+        fs::create_dir_all(root.as_ref())?;
+        Ok(Self {
+            root: root.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path_for(&self, key: &str) -> Result<PathBuf> {
+        if key.len() < 3 || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow!("invalid blob key {:?}", key));
+        }
+        let shard = &key[key.len() - 3..key.len() - 1];
+        Ok(self.root.join(shard).join(key))
+    }
+
+    /// Stores `data` and returns its key and whether new bytes were written.
+    /// An existing blob that fails verification is rewritten.
+    pub fn put(&self, data: &[u8]) -> Result<(String, bool)> {
+        let key = ipfs::compute_cid(data);
+        let path = self.path_for(&key)?;
+        if let Ok(existing) = fs::read(&path) {
+            if ipfs::verify_cid(&key, &existing) {
+                return Ok((key, false));
+            }
+            warn!("Blob {} is corrupt on disk, rewriting", key);
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write then rename so readers never see a partial blob. The temp
+        // name is unique per writer, since the daemon and CLI commands may
+        // store the same content at once.
+        let tmp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+            .and_then(|mut file| {
+                file.write_all(data)?;
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&tmp, &path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+        info!("Stored blob {} ({} bytes)", key, data.len());
+        Ok((key, true))
+    }
+
+    /// Reads a blob, failing if its bytes no longer match its key.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let data = match fs::read(self.path_for(key)?) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if !ipfs::verify_cid(key, &data) {
+            return Err(anyhow!("blob {} failed checksum verification", key));
+        }
+        Ok(Some(data))
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.path_for(key).map(|path| path.exists()).unwrap_or(false)
+    }
+
+    /// Every key in the store, for integrity sweeps. Leftover temp files
+    /// from interrupted writes are skipped.
+    pub fn keys(&self) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(shard.path())? {
+                let name = entry?.file_name();
+                if let Some(name) = name.to_str().filter(|n| !n.ends_with(".tmp")) {
+                    keys.push(name.to_string());
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Deletes a blob. Returns whether it existed.
+    pub fn remove(&self, key: &str) -> Result<bool> {
+        match fs::remove_file(self.path_for(key)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_get_dedup() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("nsd-blobs-{}", std::process::id()));
+        let blobs = BlobStore::new(&root)?;
+
+        let (key, written) = blobs.put(b"manifest payload")?;
+        assert!(written);
+        let (again, written) = blobs.put(b"manifest payload")?;
+        assert_eq!(key, again);
+        assert!(!written);
+        assert_eq!(blobs.get(&key)?.unwrap(), b"manifest payload");
+
+        // Tampered bytes are detected on read
+        fs::write(blobs.path_for(&key)?, b"tampered")?;
+        assert!(blobs.get(&key).is_err());
+
+        assert!(blobs.remove(&key)?);
+        assert!(blobs.get(&key)?.is_none());
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_puts() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("nsd-blobs-concurrent-{}", std::process::id()));
+        let blobs = std::sync::Arc::new(BlobStore::new(&root)?);
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let blobs = blobs.clone();
+                std::thread::spawn(move || blobs.put(b"same payload").map(|(key, _)| key))
+            })
+            .collect();
+        for writer in writers {
+            let key = writer.join().unwrap()?;
+            assert_eq!(blobs.get(&key)?.unwrap(), b"same payload");
+            // No temp files are left behind in the shard
+            let shard = blobs.path_for(&key)?.parent().unwrap().to_path_buf();
+            assert_eq!(fs::read_dir(shard)?.count(), 1);
+        }
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
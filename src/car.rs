@@ -0,0 +1,546 @@
+use std::io::{ErrorKind, Read, Write};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::anchor;
+use crate::ingest::{self, Admission};
+use crate::ipfs;
+use crate::manifest::Manifest;
+use crate::schema::SchemaRegistry;
+use crate::storage::{Attestation, ManifestCursor, ManifestQuery, ManifestRejection, MergeOutcome, Provenance};
+use crate::store::CatalogStore;
+
+/// Format version of the provenance sidecar block.
+const SIDECAR_VERSION: u32 = 1;
+
+/// Upper bound on a single section, so a corrupt length prefix can't make
+/// us allocate gigabytes.
+const MAX_SECTION_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Catalog metadata that CAR blocks can't carry on their own. Written as the
+/// archive's root block, so every export is a single self-describing file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CarSidecar {
+    pub version: u32,
+    pub exported_at: i64,
+    pub manifests: Vec<SidecarEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SidecarEntry {
+    pub cid: String,
+    pub timestamp: i64,
+    /// Informational only: imports look provenance up on-chain again.
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    #[serde(default)]
+    pub attestations: Vec<Attestation>,
+}
+
+/// Which manifests to export. Empty `cids` means all of them.
+#[derive(Debug, Default)]
+pub struct ExportFilter {
+    pub cids: Vec<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Only manifests in these namespaces; `None` for all of them.
+    pub namespaces: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub unchanged: usize,
+    pub conflicts: usize,
+    /// Tombstoned here or retracted by their creator.
+    pub skipped_tombstoned: usize,
+    /// Failed the signature, schema or anchor check; see `catalog rejections`.
+    pub rejected: usize,
+    pub rejected_attestations: usize,
+}
+
+/// Writes a CARv1 archive: a DAG-CBOR header naming one root, followed by
+/// `varint(len) || cid || data` sections.
+pub struct CarWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CarWriter<W> {
+    pub fn new(mut writer: W, root: &str) -> Result<Self> {
+        let header = encode_header(&cid_bytes(root)?);
+        write_varint(&mut writer, header.len() as u64)?;
+        writer.write_all(&header)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write_block(&mut self, cid: &str, data: &[u8]) -> Result<()> {
+        let cid = cid_bytes(cid)?;
+        write_varint(&mut self.writer, (cid.len() + data.len()) as u64)?;
+        self.writer.write_all(&cid)?;
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads a CARv1 archive, verifying each block against its CID.
+pub struct CarReader<R: Read> {
+    reader: R,
+    roots: Vec<String>,
+}
+
+impl<R: Read> CarReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let len = read_varint(&mut reader)?.ok_or_else(|| anyhow!("empty CAR file"))?;
+        let header = read_section(&mut reader, len)?;
+        let roots = decode_header(&header)?;
+        Ok(Self { reader, roots })
+    }
+
+    pub fn roots(&self) -> &[String] {
+        &self.roots
+    }
+
+    /// The next `(cid, data)` block, or `None` at end of file. Fails on a
+    /// block whose bytes don't hash to its CID.
+    pub fn next_block(&mut self) -> Result<Option<(String, Vec<u8>)>> {
+        let Some(len) = read_varint(&mut self.reader)? else {
+            return Ok(None);
+        };
+        let section = read_section(&mut self.reader, len)?;
+        // CIDv0 is a bare sha2-256 multihash; CIDv1 would start with 0x01
+        if section.len() < 34 || section[..2] != [0x12, 0x20] {
+            bail!("unsupported CID in CAR block (only CIDv0 is supported)");
+        }
+        let (cid, data) = section.split_at(34);
+        let cid = bs58::encode(cid).into_string();
+        if !ipfs::verify_cid(&cid, data) {
+            bail!("block {} failed verification", cid);
+        }
+        Ok(Some((cid, data.to_vec())))
+    }
+}
+
+/// Exports manifests matching `filter`, with their provenance and
+/// attestations, to `writer`. Returns how many manifests were written.
+pub fn export_catalog<W: Write>(store: &dyn CatalogStore, writer: W, filter: &ExportFilter) -> Result<usize> {
+    // Collect metadata first: the sidecar is the root, and the header must
+    // name it before any block is written
+    let mut cids = Vec::new();
+    if filter.cids.is_empty() {
+        let mut query = ManifestQuery {
+            since: filter.since,
+            until: filter.until,
+            namespaces: filter.namespaces.clone(),
+            limit: Some(500),
+            ..Default::default()
+        };
+        loop {
+            let page = store.query_manifest_summaries(&query)?;
+            let Some(last) = page.last() else { break };
+            query.after = Some(ManifestCursor { timestamp: last.timestamp, cid: last.cid.clone() });
+            cids.extend(page.into_iter().map(|m| m.cid));
+        }
+    } else {
+        cids.extend(filter.cids.iter().cloned());
+    }
+
+    let mut entries = Vec::new();
+    for cid in cids {
+        let Some(manifest) = store.get_manifest(&cid)? else {
+            warn!("Skipping {}: not in the catalog", cid);
+            continue;
+        };
+        if !in_range(&manifest, filter) {
+            continue;
+        }
+        if !ipfs::verify_cid(&manifest.cid, &manifest.data) {
+            warn!("Skipping {}: data does not hash to its CID", manifest.cid);
+            continue;
+        }
+        entries.push(SidecarEntry {
+            provenance: store.get_provenance(&cid)?,
+            attestations: store.get_attestations(&cid)?,
+            cid,
+            timestamp: manifest.timestamp,
+        });
+    }
+
+    let sidecar = CarSidecar {
+        version: SIDECAR_VERSION,
+        exported_at: chrono::Utc::now().timestamp(),
+        manifests: entries,
+    };
+    let sidecar_block = serde_json::to_vec(&sidecar)?;
+    let root = ipfs::compute_cid(&sidecar_block);
+    let mut car = CarWriter::new(writer, &root)?;
+    car.write_block(&root, &sidecar_block)?;
+
+    for entry in &sidecar.manifests {
+        let manifest = store
+            .get_manifest(&entry.cid)?
+            .ok_or_else(|| anyhow!("manifest {} disappeared during export", entry.cid))?;
+        car.write_block(&manifest.cid, &manifest.data)?;
+    }
+    car.finish()?;
+    info!("Exported {} manifests", sidecar.manifests.len());
+    Ok(sidecar.manifests.len())
+}
+
+/// Imports an archive written by `export_catalog`. Every block is verified
+/// before anything is stored, so a damaged archive imports nothing. Each
+/// manifest then goes through the same checks as one from a peer:
+/// `ingest::admit`, then `anchored(cid, creator)`. The archive's provenance
+/// is not trusted; the provenance cache fetches it again when needed.
+pub fn import_catalog<R: Read>(
+    store: &dyn CatalogStore,
+    reader: R,
+    source: &str,
+    schemas: &SchemaRegistry,
+    anchored: &mut dyn FnMut(&str, &str) -> Result<bool>,
+) -> Result<ImportSummary> {
+    let mut car = CarReader::new(reader)?;
+    let [root] = car.roots() else {
+        bail!("expected exactly one root, found {}", car.roots().len());
+    };
+    let root = root.clone();
+
+    let mut sidecar = None;
+    let mut blocks = std::collections::HashMap::new();
+    while let Some((cid, data)) = car.next_block()? {
+        if cid == root {
+            sidecar = Some(serde_json::from_slice::<CarSidecar>(&data)?);
+        } else {
+            blocks.insert(cid, data);
+        }
+    }
+    let sidecar = sidecar.ok_or_else(|| anyhow!("archive is missing its sidecar block {}", root))?;
+    if sidecar.version > SIDECAR_VERSION {
+        bail!("sidecar version {} is newer than supported version {}", sidecar.version, SIDECAR_VERSION);
+    }
+
+    if let Some(entry) = sidecar.manifests.iter().find(|e| !blocks.contains_key(&e.cid)) {
+        bail!("archive lists {} but has no block for it", entry.cid);
+    }
+
+    let mut summary = ImportSummary::default();
+    for entry in sidecar.manifests {
+        let Some(data) = blocks.remove(&entry.cid) else { continue };
+        let manifest = Manifest { cid: entry.cid.clone(), data, timestamp: entry.timestamp };
+        let rejection = match ingest::admit(store, schemas, &manifest)? {
+            Admission::Admitted(document) => match anchored(&manifest.cid, &document.creator) {
+                Ok(true) => None,
+                _ => Some("anchor verification failed".to_string()),
+            },
+            Admission::Tombstoned | Admission::Retracted => {
+                summary.skipped_tombstoned += 1;
+                continue;
+            }
+            Admission::Invalid(reason) => Some(reason),
+        };
+        if let Some(reason) = rejection {
+            warn!("Rejected {} from {}: {}", manifest.cid, source, reason);
+            store.record_rejection(&ManifestRejection {
+                cid: manifest.cid.clone(),
+                source: source.to_string(),
+                reason,
+                rejected_at: chrono::Utc::now().timestamp(),
+            })?;
+            summary.rejected += 1;
+            continue;
+        }
+        match store.merge_manifest(&manifest, source, None)? {
+            MergeOutcome::Inserted => summary.inserted += 1,
+            MergeOutcome::Conflict => {
+                summary.conflicts += 1;
+                continue;
+            }
+            _ => summary.unchanged += 1,
+        }
+        for attestation in &entry.attestations {
+            if attestation.manifest_cid == entry.cid && anchor::verify_attestation(attestation).unwrap_or(false) {
+                store.insert_attestation(attestation)?;
+            } else {
+                summary.rejected_attestations += 1;
+            }
+        }
+    }
+    if !blocks.is_empty() {
+        warn!("Ignored {} blocks not listed in the sidecar", blocks.len());
+    }
+    info!("Imported {} manifests from {}", summary.inserted, source);
+    Ok(summary)
+}
+
+fn in_range(manifest: &Manifest, filter: &ExportFilter) -> bool {
+    filter.since.map_or(true, |since| manifest.timestamp > since)
+        && filter.until.map_or(true, |until| manifest.timestamp <= until)
+        && filter.namespaces.as_ref().map_or(true, |namespaces| namespaces.contains(&manifest.namespace()))
+}
+
+fn cid_bytes(cid: &str) -> Result<Vec<u8>> {
+    let bytes = bs58::decode(cid).into_vec()?;
+    if !cid.starts_with("Qm") || bytes.len() != 34 {
+        bail!("{} is not a CIDv0", cid);
+    }
+    Ok(bytes)
+}
+
+fn read_section<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>> {
+    if len > MAX_SECTION_SIZE {
+        bail!("CAR section of {} bytes exceeds the {} byte limit", len, MAX_SECTION_SIZE);
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> Result<()> {
+    let mut buf = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+/// Reads an unsigned LEB128 varint; `None` on a clean end of file.
+fn read_varint<R: Read>(reader: &mut R) -> Result<Option<u64>> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8; 1];
+        match reader.read_exact(&mut byte) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof && i == 0 => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        value |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    bail!("varint too long")
+}
+
+/// DAG-CBOR `{"roots": [root], "version": 1}`, keys in canonical order.
+fn encode_header(root: &[u8]) -> Vec<u8> {
+    let mut out = vec![0xa2];
+    cbor_head(&mut out, 3, 5);
+    out.extend_from_slice(b"roots");
+    cbor_head(&mut out, 4, 1);
+    // CIDs are tag 42 over the binary CID with a leading multibase 0x00
+    out.extend_from_slice(&[0xd8, 0x2a]);
+    cbor_head(&mut out, 2, root.len() as u64 + 1);
+    out.push(0x00);
+    out.extend_from_slice(root);
+    cbor_head(&mut out, 3, 7);
+    out.extend_from_slice(b"version");
+    cbor_head(&mut out, 0, 1);
+    out
+}
+
+fn cbor_head(out: &mut Vec<u8>, major: u8, len: u64) {
+    let major = major << 5;
+    match len {
+        0..=23 => out.push(major | len as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, len as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&len.to_be_bytes());
+        }
+    }
+}
+
+/// Parses just enough DAG-CBOR to read a CARv1 header's roots and version.
+fn decode_header(buf: &[u8]) -> Result<Vec<String>> {
+    let mut pos = 0;
+    let (major, entries) = cbor_read_head(buf, &mut pos)?;
+    if major != 5 {
+        bail!("CAR header is not a map");
+    }
+    let mut roots = None;
+    let mut version = None;
+    for _ in 0..entries {
+        let key = cbor_read_text(buf, &mut pos)?;
+        match key.as_str() {
+            "roots" => {
+                let (major, count) = cbor_read_head(buf, &mut pos)?;
+                if major != 4 {
+                    bail!("CAR header roots is not an array");
+                }
+                let mut cids = Vec::new();
+                for _ in 0..count {
+                    let (major, tag) = cbor_read_head(buf, &mut pos)?;
+                    if major != 6 || tag != 42 {
+                        bail!("CAR header root is not a CID");
+                    }
+                    let bytes = cbor_read_bytes(buf, &mut pos)?;
+                    match bytes.split_first() {
+                        Some((0x00, cid)) if cid.len() == 34 && cid[..2] == [0x12, 0x20] => {
+                            cids.push(bs58::encode(cid).into_string())
+                        }
+                        _ => bail!("unsupported root CID (only CIDv0 is supported)"),
+                    }
+                }
+                roots = Some(cids);
+            }
+            "version" => {
+                let (major, value) = cbor_read_head(buf, &mut pos)?;
+                if major != 0 {
+                    bail!("CAR header version is not an integer");
+                }
+                version = Some(value);
+            }
+            other => bail!("unexpected CAR header key {:?}", other),
+        }
+    }
+    match version {
+        Some(1) => {}
+        Some(v) => bail!("unsupported CAR version {}", v),
+        None => bail!("CAR header has no version"),
+    }
+    roots.ok_or_else(|| anyhow!("CAR header has no roots"))
+}
+
+fn cbor_read_head(buf: &[u8], pos: &mut usize) -> Result<(u8, u64)> {
+    let first = *buf.get(*pos).ok_or_else(|| anyhow!("truncated CAR header"))?;
+    *pos += 1;
+    let extra = match first & 0x1f {
+        n @ 0..=23 => return Ok((first >> 5, n as u64)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => bail!("unsupported CBOR encoding in CAR header"),
+    };
+    let bytes = buf.get(*pos..*pos + extra).ok_or_else(|| anyhow!("truncated CAR header"))?;
+    *pos += extra;
+    Ok((first >> 5, bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64)))
+}
+
+fn cbor_read_bytes<'a>(buf: &'a [u8], pos: &mut usize) -> Result<&'a [u8]> {
+    let (major, len) = cbor_read_head(buf, pos)?;
+    if major != 2 && major != 3 {
+        bail!("expected a CBOR string in CAR header");
+    }
+    // `len` comes from the file; a huge one must not wrap around
+    let end = usize::try_from(len)
+        .ok()
+        .and_then(|len| pos.checked_add(len))
+        .ok_or_else(|| anyhow!("truncated CAR header"))?;
+    let bytes = buf.get(*pos..end).ok_or_else(|| anyhow!("truncated CAR header"))?;
+    *pos = end;
+    Ok(bytes)
+}
+
+fn cbor_read_text(buf: &[u8], pos: &mut usize) -> Result<String> {
+    Ok(String::from_utf8(cbor_read_bytes(buf, pos)?.to_vec())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::publish;
+    use crate::retraction::Retraction;
+    use crate::store::MemoryStore;
+    use solana_sdk::signature::Keypair;
+
+    fn manifest(keypair: &Keypair, name: &str, timestamp: i64) -> Manifest {
+        let draft = serde_json::json!({ "name": name });
+        publish::finish(draft, keypair, "node1", timestamp, &[]).unwrap().1
+    }
+
+    fn import(target: &MemoryStore, archive: &[u8], anchored: bool) -> Result<ImportSummary> {
+        import_catalog(target, archive, "test.car", &SchemaRegistry::builtin(), &mut |_, _| Ok(anchored))
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() -> Result<()> {
+        let keypair = Keypair::new();
+        let source = MemoryStore::new();
+        let manifests: Vec<Manifest> =
+            ["alpha", "beta", "gamma"].iter().enumerate().map(|(i, name)| manifest(&keypair, name, 100 + i as i64)).collect();
+        for m in &manifests {
+            source.insert_manifest(m)?;
+        }
+        let provenance = Provenance { finalized: true, attestation_count: 2, tx_signature: "sig".to_string(), slot: 9, anchored_at: None };
+        source.cache_provenance(&manifests[1].cid, &provenance)?;
+
+        let mut archive = Vec::new();
+        let filter = ExportFilter { since: Some(100), ..Default::default() };
+        assert_eq!(export_catalog(&source, &mut archive, &filter)?, 2);
+
+        let target = MemoryStore::new();
+        let summary = import(&target, &archive, true)?;
+        assert_eq!(summary.inserted, 2);
+        assert!(target.get_manifest(&manifests[0].cid)?.is_none());
+        assert_eq!(target.get_manifest(&manifests[2].cid)?.unwrap().timestamp, 102);
+        // The archive's provenance isn't trusted
+        assert!(target.get_provenance(&manifests[1].cid)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_checks_like_sync() -> Result<()> {
+        let keypair = Keypair::new();
+        let source = MemoryStore::new();
+        let unsigned = br#"{"schema_version":1,"creator":"Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS","node_id":"n","created_at":1}"#;
+        source.insert_manifest(&Manifest { cid: ipfs::compute_cid(unsigned), data: unsigned.to_vec(), timestamp: 1 })?;
+        let retracted = manifest(&keypair, "leaked", 2);
+        source.insert_manifest(&retracted)?;
+        let mut archive = Vec::new();
+        export_catalog(&source, &mut archive, &ExportFilter::default())?;
+
+        let target = MemoryStore::new();
+        target.record_retraction(&Retraction::new(&retracted.cid, "leaked", &keypair, 3), None, None)?;
+        let summary = import(&target, &archive, true)?;
+        assert_eq!((summary.inserted, summary.rejected, summary.skipped_tombstoned), (0, 1, 1));
+
+        // Signed but not anchored
+        let source = MemoryStore::new();
+        source.insert_manifest(&manifest(&keypair, "draft", 4))?;
+        let mut archive = Vec::new();
+        export_catalog(&source, &mut archive, &ExportFilter::default())?;
+        assert_eq!(import(&target, &archive, false)?.rejected, 1);
+        assert_eq!(target.count_manifests(&ManifestQuery::default())?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_corrupt_block() -> Result<()> {
+        let source = MemoryStore::new();
+        source.insert_manifest(&manifest(&Keypair::new(), "payload", 1))?;
+        let mut archive = Vec::new();
+        export_catalog(&source, &mut archive, &ExportFilter::default())?;
+
+        // Flip the last byte of the manifest block
+        let last = archive.len() - 1;
+        archive[last] ^= 0xff;
+        let target = MemoryStore::new();
+        assert!(import(&target, &archive, true).is_err());
+        assert_eq!(target.count_manifests(&ManifestQuery::default())?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_length_overflow() {
+        // Byte string claiming u64::MAX bytes
+        let header = [0x5b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+        assert!(cbor_read_bytes(&header, &mut 0).is_err());
+    }
+}
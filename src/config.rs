@@ -1,10 +1,28 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 
+use crate::storage::CatalogFilter;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     pub network: NetworkConfig,
     pub node: NodeConfig,
     pub solana: SolanaConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub backup: BackupConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub provenance_cache: ProvenanceCacheConfig,
+    #[serde(default)]
+    pub schemas: SchemaConfig,
+    #[serde(default)]
+    pub namespaces: NamespaceConfig,
+    /// The manifests this node requests from and accepts from peers.
+    #[serde(default)]
+    pub replicate: CatalogFilter,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -13,6 +31,18 @@ pub struct NetworkConfig {
     pub static_peers: Vec<String>,
     pub listen_addr: String,
     pub max_peers: usize,
+    /// Directory holding this node's certificate (`node.crt`) and key. The
+    /// certificate's fingerprint is the node's id on the network.
+    #[serde(default = "default_identity_dir")]
+    pub identity_dir: String,
+    /// Accept peers whose certificate doesn't match the node id they claim.
+    /// For debugging only.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+fn default_identity_dir() -> String {
+    ".".to_string()
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -20,10 +50,156 @@ pub struct NodeConfig {
     pub node_id: String,
     pub version: String,
     pub mode: String, // validator, gateway, indexer, full
+    /// Solana keypair file (as written by `solana-keygen`) used to sign and
+    /// anchor manifests published with `nsd catalog add`, and by the index
+    /// to open private manifests sealed to this node.
+    #[serde(default)]
+    pub keypair: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SolanaConfig {
     pub rpc_url: String,
     pub program_id: String,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// `sqlite` (catalog.db) or `memory` for ephemeral nodes that keep nothing across restarts.
+    pub backend: String,
+    /// How long to remember locally evicted CIDs so sync doesn't re-import them.
+    pub evicted_tombstone_days: u32,
+    /// How long to keep and re-advertise retractions to peers.
+    pub retracted_tombstone_days: u32,
+    /// Unpin a manifest and its artifacts from IPFS when its creator retracts it.
+    pub unpin_retracted: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: "sqlite".to_string(),
+            evicted_tombstone_days: 30,
+            retracted_tombstone_days: 365,
+            unpin_retracted: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Take periodic online backups of catalog.db while the daemon runs.
+    pub enabled: bool,
+    /// Directory for scheduled backups, named `catalog-<UTC time>.db`.
+    pub dir: String,
+    pub interval_hours: u64,
+    /// Number of scheduled backups to keep; older ones are deleted.
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "backups".to_string(),
+            interval_hours: 24,
+            keep: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Evict manifests older than this. Unset keeps them regardless of age.
+    pub max_age_days: Option<u32>,
+    /// Evict oldest manifests until payloads fit in this many MiB.
+    pub max_catalog_mb: Option<u64>,
+    /// Never evict manifests whose anchor is finalized on-chain.
+    pub keep_finalized: bool,
+    /// Never evict manifests pinned in the local IPFS node.
+    pub keep_pinned: bool,
+    /// Never evict ancestors of a manifest that stays.
+    pub keep_lineage_ancestors: bool,
+    /// How often the daemon's janitor applies the policy.
+    pub janitor_interval_minutes: u64,
+    /// Limits for individual namespaces, replacing the two above. Each
+    /// listed namespace gets its own size budget; the rest share one.
+    pub namespaces: BTreeMap<String, NamespaceRetention>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NamespaceRetention {
+    pub max_age_days: Option<u32>,
+    pub max_catalog_mb: Option<u64>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            max_catalog_mb: None,
+            keep_finalized: true,
+            keep_pinned: true,
+            keep_lineage_ancestors: true,
+            janitor_interval_minutes: 60,
+            namespaces: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProvenanceCacheConfig {
+    /// How long a cached finalized anchor is trusted before re-querying Solana.
+    pub finalized_ttl_secs: u64,
+    /// Unfinalized anchors can still change, so they expire quickly.
+    pub unfinalized_ttl_secs: u64,
+}
+
+impl Default for ProvenanceCacheConfig {
+    fn default() -> Self {
+        Self {
+            finalized_ttl_secs: 86400,
+            unfinalized_ttl_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SchemaConfig {
+    /// Directory of `<type>.json` JSON Schemas, added to the built-in ones
+    /// and replacing any with the same name.
+    pub dir: Option<String>,
+    /// Accept manifests whose `type` has no schema. Manifests without a
+    /// `type` are always accepted.
+    pub allow_unknown_types: bool,
+}
+
+impl Default for SchemaConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            allow_unknown_types: true,
+        }
+    }
+}
+
+/// Which catalog namespaces this node offers to peers. What it takes from
+/// them is `Config::replicate`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NamespaceConfig {
+    /// Namespaces offered to peers; empty offers every namespace.
+    pub serve: Vec<String>,
+}
+
+impl NamespaceConfig {
+    pub fn serves(&self, namespace: &str) -> bool {
+        self.serve.is_empty() || self.serve.iter().any(|n| n == namespace)
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha512};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+
+use crate::manifest::{ArtifactRef, ManifestDocument};
+
+pub const ALGORITHM: &str = "x25519-chacha20poly1305";
+
+/// Encrypted private fields of a manifest, readable by a set of Solana keys.
+///
+/// The fields are encrypted once under a random content key; that key is
+/// wrapped for each recipient with an X25519 agreement between a per-manifest
+/// ephemeral key and the recipient's Ed25519 key in Montgomery form. The
+/// envelope is part of the signed document, so authorship, the CID and the
+/// anchor can all be checked without reading it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub algorithm: String,
+    /// Base64 X25519 public key of the sender's ephemeral secret.
+    pub ephemeral_key: String,
+    pub recipients: Vec<Recipient>,
+    /// Base64 12-byte nonce for `ciphertext`.
+    pub nonce: String,
+    /// Base64 ChaCha20-Poly1305 ciphertext of the private fields.
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipient {
+    /// Base58 Solana public key.
+    pub key: String,
+    /// Base64 content key, encrypted to `key`.
+    pub wrapped_key: String,
+}
+
+/// What goes inside the envelope: everything that describes the content
+/// rather than who published it.
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    #[serde(default)]
+    parents: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    artifacts: Vec<ArtifactRef>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, Value>,
+}
+
+impl Envelope {
+    pub fn is_recipient(&self, key: &Pubkey) -> bool {
+        let key = key.to_string();
+        self.recipients.iter().any(|r| r.key == key)
+    }
+
+    /// Structural checks only; nothing here needs a key.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.algorithm != ALGORITHM {
+            return Err(format!("unsupported algorithm `{}`", self.algorithm));
+        }
+        if self.recipients.is_empty() {
+            return Err("no recipients".to_string());
+        }
+        for recipient in &self.recipients {
+            if Pubkey::from_str(&recipient.key).is_err() {
+                return Err(format!("recipient `{}` is not a base58 public key", recipient.key));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Moves `document`'s parents, tags, artifacts and type-specific fields into
+/// an envelope readable by `recipients`. Sign the document afterwards.
+pub fn seal(document: &mut ManifestDocument, recipients: &[Pubkey]) -> Result<()> {
+    let recipients: BTreeSet<&Pubkey> = recipients.iter().collect();
+    if recipients.is_empty() {
+        bail!("a private manifest needs at least one recipient");
+    }
+    let sealed = Sealed {
+        parents: std::mem::take(&mut document.parents),
+        tags: std::mem::take(&mut document.tags),
+        artifacts: std::mem::take(&mut document.artifacts),
+        extra: std::mem::take(&mut document.extra),
+    };
+    let content_key: [u8; 32] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let ciphertext = cipher(&content_key)
+        .encrypt(Nonce::from_slice(&nonce), serde_json::to_vec(&sealed)?.as_ref())
+        .map_err(|_| anyhow!("encrypting manifest fields failed"))?;
+
+    let ephemeral = StaticSecret::from(rand::random::<[u8; 32]>());
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let mut wrapped = Vec::new();
+    for key in recipients {
+        let public = x25519_public(key)?;
+        let wrapping_key = wrapping_key(&ephemeral.diffie_hellman(&public), &ephemeral_public, &public);
+        let wrapped_key = cipher(&wrapping_key)
+            .encrypt(Nonce::from_slice(&[0; 12]), content_key.as_ref())
+            .map_err(|_| anyhow!("wrapping content key failed"))?;
+        wrapped.push(Recipient { key: key.to_string(), wrapped_key: BASE64.encode(wrapped_key) });
+    }
+    document.encrypted = Some(Envelope {
+        algorithm: ALGORITHM.to_string(),
+        ephemeral_key: BASE64.encode(ephemeral_public.as_bytes()),
+        recipients: wrapped,
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    });
+    Ok(())
+}
+
+/// Decrypts a sealed document with `keypair`, returning it with the private
+/// fields restored and no envelope. The result is for reading: its
+/// `signature` covers the sealed form, so check authorship before opening.
+pub fn open(document: &ManifestDocument, keypair: &Keypair) -> Result<ManifestDocument> {
+    let envelope = document.encrypted.as_ref().ok_or_else(|| anyhow!("manifest is not encrypted"))?;
+    let me = keypair.pubkey().to_string();
+    let recipient = envelope
+        .recipients
+        .iter()
+        .find(|r| r.key == me)
+        .ok_or_else(|| anyhow!("{} is not a recipient", me))?;
+
+    let secret = x25519_secret(keypair);
+    let ephemeral_public = PublicKey::from(decode_array::<32>(&envelope.ephemeral_key, "ephemeral_key")?);
+    let wrapping_key = wrapping_key(&secret.diffie_hellman(&ephemeral_public), &ephemeral_public, &PublicKey::from(&secret));
+    let content_key = cipher(&wrapping_key)
+        .decrypt(Nonce::from_slice(&[0; 12]), decode(&recipient.wrapped_key, "wrapped_key")?.as_ref())
+        .map_err(|_| anyhow!("cannot unwrap the content key for {}", me))?;
+    let nonce = decode_array::<12>(&envelope.nonce, "nonce")?;
+    let plaintext = cipher(&content_key)
+        .decrypt(Nonce::from_slice(&nonce), decode(&envelope.ciphertext, "ciphertext")?.as_ref())
+        .map_err(|_| anyhow!("manifest ciphertext failed authentication"))?;
+    let sealed: Sealed = serde_json::from_slice(&plaintext).context("parsing decrypted manifest fields")?;
+
+    let mut extra = document.extra.clone();
+    extra.extend(sealed.extra);
+    let opened = ManifestDocument {
+        parents: sealed.parents,
+        tags: sealed.tags,
+        artifacts: sealed.artifacts,
+        extra,
+        encrypted: None,
+        ..document.clone()
+    };
+    opened.validate()?;
+    Ok(opened)
+}
+
+fn cipher(key: &[u8]) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+}
+
+/// Binds the shared secret to both public keys, so a wrapped key can't be
+/// replayed under another recipient or ephemeral key.
+fn wrapping_key(shared: &SharedSecret, ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"neuroswarm-envelope:");
+    hasher.update(shared.as_bytes());
+    hasher.update(ephemeral.as_bytes());
+    hasher.update(recipient.as_bytes());
+    hasher.finalize().into()
+}
+
+/// The X25519 secret matching a Solana keypair: the clamped Ed25519 scalar.
+fn x25519_secret(keypair: &Keypair) -> StaticSecret {
+    let digest = Sha512::digest(&keypair.to_bytes()[..32]);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&digest[..32]);
+    StaticSecret::from(scalar)
+}
+
+/// The Montgomery form of a Solana (Ed25519) public key.
+fn x25519_public(key: &Pubkey) -> Result<PublicKey> {
+    let point = CompressedEdwardsY(key.to_bytes())
+        .decompress()
+        .ok_or_else(|| anyhow!("{} is not a valid Ed25519 key", key))?;
+    Ok(PublicKey::from(point.to_montgomery().to_bytes()))
+}
+
+fn decode(value: &str, field: &str) -> Result<Vec<u8>> {
+    BASE64.decode(value).with_context(|| format!("envelope `{}` is not base64", field))
+}
+
+fn decode_array<const N: usize>(value: &str, field: &str) -> Result<[u8; N]> {
+    decode(value, field)?
+        .try_into()
+        .map_err(|_| anyhow!("envelope `{}` must be {} bytes", field, N))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() -> Result<()> {
+        let creator = Keypair::new();
+        let reader = Keypair::new();
+        let mut document = ManifestDocument::parse(
+            serde_json::json!({
+                "schema_version": 1,
+                "type": "model",
+                "creator": creator.pubkey().to_string(),
+                "node_id": "node1",
+                "tags": ["llm"],
+                "framework": "pytorch",
+                "created_at": 1700000000
+            })
+            .to_string()
+            .as_bytes(),
+        )?;
+        let plain = document.clone();
+        seal(&mut document, &[creator.pubkey(), reader.pubkey()])?;
+        document.sign(&creator)?;
+
+        // Sealed form hides the content but keeps who and what kind
+        let sealed = ManifestDocument::parse(&document.to_bytes())?;
+        assert!(sealed.tags.is_empty() && sealed.extra.is_empty());
+        assert_eq!(sealed.kind.as_deref(), Some("model"));
+        sealed.verify_signature()?;
+
+        for keypair in [&creator, &reader] {
+            let opened = open(&sealed, keypair)?;
+            assert_eq!(ManifestDocument { signature: None, ..opened }, plain);
+        }
+        assert!(open(&sealed, &Keypair::new()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+use anyhow::{bail, Result};
+use rusqlite::{params, Connection, Transaction, TransactionBehavior};
+use tracing::{info, warn};
+
+use crate::blobs::BlobStore;
+use crate::ipfs;
+use crate::migrations;
+
+/// Problems found by `check`. Everything except `integrity_errors` can be
+/// fixed by `repair`.
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// Output of `PRAGMA integrity_check` other than "ok".
+    pub integrity_errors: Vec<String>,
+    /// `(cid, problem)` for manifests whose payload is missing, corrupt or
+    /// doesn't hash to the CID.
+    pub bad_manifests: Vec<(String, String)>,
+    pub orphaned_provenance: Vec<String>,
+    /// `(id, manifest_cid)` of attestations whose manifest is gone.
+    pub orphaned_attestations: Vec<(i64, String)>,
+    /// CIDs with `manifest_tags` rows but no manifest.
+    pub orphaned_tags: Vec<String>,
+    /// Blobs no manifest row references.
+    pub orphaned_blobs: Vec<String>,
+}
+
+impl FsckReport {
+    pub fn problem_count(&self) -> usize {
+        self.integrity_errors.len()
+            + self.bad_manifests.len()
+            + self.orphaned_provenance.len()
+            + self.orphaned_attestations.len()
+            + self.orphaned_tags.len()
+            + self.orphaned_blobs.len()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.problem_count() == 0
+    }
+}
+
+/// Checks `catalog` and its blob directory without changing anything.
+pub fn check(catalog: &Path) -> Result<FsckReport> {
+    let conn = open(catalog)?;
+    let mut report = FsckReport::default();
+
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    for row in stmt.query_map([], |row| row.get::<_, String>(0))? {
+        let row = row?;
+        if row != "ok" {
+            report.integrity_errors.push(row);
+        }
+    }
+
+    // foreign_key_check finds these too, but only reports rowids
+    let mut stmt = conn.prepare("SELECT cid FROM provenance WHERE cid NOT IN (SELECT cid FROM manifests)")?;
+    for row in stmt.query_map([], |row| row.get(0))? {
+        report.orphaned_provenance.push(row?);
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id, manifest_cid FROM attestations WHERE manifest_cid NOT IN (SELECT cid FROM manifests)",
+    )?;
+    for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+        report.orphaned_attestations.push(row?);
+    }
+    let mut stmt = conn.prepare("SELECT DISTINCT cid FROM manifest_tags WHERE cid NOT IN (SELECT cid FROM manifests)")?;
+    for row in stmt.query_map([], |row| row.get(0))? {
+        report.orphaned_tags.push(row?);
+    }
+
+    let blobs = BlobStore::new(catalog.with_extension("blobs"))?;
+    let mut referenced = HashSet::new();
+    let mut stmt = conn.prepare("SELECT cid, data, blob_key FROM manifests")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<Vec<u8>>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+        ))
+    })?;
+    for row in rows {
+        let (cid, data, blob_key) = row?;
+        if let Some(key) = &blob_key {
+            referenced.insert(key.clone());
+        }
+        let data = match (data, blob_key) {
+            (Some(data), _) => data,
+            (None, Some(key)) => match blobs.get(&key) {
+                Ok(Some(data)) => data,
+                Ok(None) => {
+                    report.bad_manifests.push((cid, format!("blob {} is missing", key)));
+                    continue;
+                }
+                Err(e) => {
+                    report.bad_manifests.push((cid, e.to_string()));
+                    continue;
+                }
+            },
+            (None, None) => {
+                report.bad_manifests.push((cid, "no payload".to_string()));
+                continue;
+            }
+        };
+        if !ipfs::verify_cid(&cid, &data) {
+            report.bad_manifests.push((cid, "data does not hash to the CID".to_string()));
+        }
+    }
+
+    for key in blobs.keys()? {
+        if !referenced.contains(&key) {
+            report.orphaned_blobs.push(key);
+        }
+    }
+    Ok(report)
+}
+
+/// Fixes what `check` found: deletes bad manifests (without a tombstone, so
+/// they can be fetched again), orphaned rows and unreferenced blobs. Returns
+/// the CIDs of the deleted manifests.
+pub fn repair(catalog: &Path, report: &FsckReport) -> Result<Vec<String>> {
+    let conn = open(catalog)?;
+    let tx = conn.unchecked_transaction()?;
+    let mut removed = Vec::new();
+    for (cid, problem) in &report.bad_manifests {
+        tx.execute("DELETE FROM provenance WHERE cid = ?1", params![cid])?;
+        tx.execute("DELETE FROM attestations WHERE manifest_cid = ?1", params![cid])?;
+        tx.execute("DELETE FROM manifests WHERE cid = ?1", params![cid])?;
+        warn!("Removed manifest {}: {}", cid, problem);
+        removed.push(cid.clone());
+    }
+    for cid in &report.orphaned_provenance {
+        tx.execute("DELETE FROM provenance WHERE cid = ?1", params![cid])?;
+    }
+    for (id, _) in &report.orphaned_attestations {
+        tx.execute("DELETE FROM attestations WHERE id = ?1", params![id])?;
+    }
+    for cid in &report.orphaned_tags {
+        tx.execute("DELETE FROM manifest_tags WHERE cid = ?1", params![cid])?;
+    }
+    tx.commit()?;
+
+    // Removing bad manifests can orphan more blobs, so sweep again. A
+    // running daemon writes each blob and its row under the write lock
+    // (see `Storage::merge_manifest`), so holding it here means every blob
+    // on disk that matters is already referenced.
+    let blobs = BlobStore::new(catalog.with_extension("blobs"))?;
+    let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)?;
+    let referenced = tx
+        .prepare("SELECT DISTINCT blob_key FROM manifests WHERE blob_key IS NOT NULL")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<HashSet<_>>>()?;
+    let mut swept = 0;
+    for key in blobs.keys()? {
+        if !referenced.contains(&key) && blobs.remove(&key)? {
+            swept += 1;
+        }
+    }
+    tx.commit()?;
+    info!(
+        "Repaired catalog: {} manifests, {} provenance rows, {} attestations, {} tag sets, {} blobs removed",
+        removed.len(),
+        report.orphaned_provenance.len(),
+        report.orphaned_attestations.len(),
+        report.orphaned_tags.len(),
+        swept
+    );
+    Ok(removed)
+}
+
+fn open(catalog: &Path) -> Result<Connection> {
+    if !catalog.exists() {
+        bail!("{} does not exist", catalog.display());
+    }
+    let conn = Connection::open(catalog)?;
+    // The daemon may hold the write lock briefly; wait for it
+    conn.busy_timeout(Duration::from_secs(5))?;
+    // Like every catalog connection, so repairs can't orphan rows themselves
+    conn.pragma_update(None, "foreign_keys", true)?;
+    // The checks rely on the latest columns (blob_key)
+    let version = migrations::current_version(&conn)?;
+    if version != migrations::latest_version() {
+        bail!(
+            "catalog is at schema v{}, expected v{}; run `nsd catalog migrate` first",
+            version,
+            migrations::latest_version()
+        );
+    }
+    Ok(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use crate::storage::{Provenance, Storage};
+    use crate::store::CatalogStore;
+
+    #[test]
+    fn test_check_and_repair() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nsd-fsck-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let catalog = dir.join("catalog.db");
+        let storage = Storage::new(&catalog)?;
+        let good = b"good".to_vec();
+        storage.insert_manifest(&Manifest { cid: ipfs::compute_cid(&good), data: good, timestamp: 1 })?;
+        // A CID that doesn't match its data
+        storage.insert_manifest(&Manifest { cid: ipfs::compute_cid(b"other"), data: b"bad".to_vec(), timestamp: 2 })?;
+        let provenance = Provenance { finalized: true, attestation_count: 0, tx_signature: "sig".to_string(), slot: 1, anchored_at: None };
+        storage.cache_provenance(&ipfs::compute_cid(b"other"), &provenance)?;
+        drop(storage);
+
+        // Connections without foreign_keys (like the old code) can orphan rows
+        let raw = Connection::open(&catalog)?;
+        raw.execute(
+            "INSERT INTO provenance (cid, finalized, attestation_count, tx_signature, slot, cached_at)
+             VALUES ('QmGone', 1, 0, 'sig', 1, 0)",
+            [],
+        )?;
+        raw.execute("INSERT INTO manifest_tags (cid, tag) VALUES ('QmGone', 'ai')", [])?;
+        BlobStore::new(catalog.with_extension("blobs"))?.put(b"stray")?;
+
+        let report = check(&catalog)?;
+        assert!(report.integrity_errors.is_empty());
+        assert_eq!(report.bad_manifests.len(), 1);
+        assert_eq!(report.orphaned_provenance, vec!["QmGone".to_string()]);
+        assert_eq!(report.orphaned_tags, vec!["QmGone".to_string()]);
+        assert_eq!(report.orphaned_blobs.len(), 1);
+
+        assert_eq!(repair(&catalog, &report)?, vec![ipfs::compute_cid(b"other")]);
+        assert!(check(&catalog)?.is_clean());
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
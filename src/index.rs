@@ -1,38 +1,417 @@
-use std::collections::HashMap;
-use crate::storage::Manifest;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use anyhow::Result;
+use solana_sdk::signature::{Keypair, Signer};
+use tracing::{debug, warn};
+use crate::envelope;
+use crate::manifest::{Manifest, ManifestDocument};
+use crate::retraction::Retraction;
+use crate::storage::ManifestQuery;
+use crate::store::{CatalogStore, ManifestPager};
+
+/// A version forked: several manifests supersede the same one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionConflict {
+    pub superseded: String,
+    pub claimants: Vec<String>,
+}
 
 pub struct Index {
     cid_index: HashMap<String, Manifest>,
+    /// Parsed documents; manifests whose data doesn't parse are indexed by
+    /// CID only.
+    documents: HashMap<String, ManifestDocument>,
     node_index: HashMap<String, Vec<String>>, // node_id -> cids
+    tag_index: HashMap<String, Vec<String>>,  // tag -> cids
+    names: HashMap<(String, String), Vec<String>>, // (namespace, name) -> cids
+    successors: HashMap<String, Vec<String>>, // cid -> cids claiming to supersede it
+    /// Withdrawn manifests, kept out of the index and of lineage fetches.
+    retracted: HashMap<String, Retraction>,
+    /// Opens private manifests sealed to this key; others are indexed by
+    /// their public fields only.
+    keypair: Option<Arc<Keypair>>,
 }
 
 impl Index {
     pub fn new() -> Self {
         Self {
             cid_index: HashMap::new(),
+            documents: HashMap::new(),
             node_index: HashMap::new(),
+            tag_index: HashMap::new(),
+            names: HashMap::new(),
+            successors: HashMap::new(),
+            retracted: HashMap::new(),
+            keypair: None,
+        }
+    }
+
+    /// An index that decrypts private manifests sealed to `keypair`.
+    pub fn with_keypair(keypair: Arc<Keypair>) -> Self {
+        Self {
+            keypair: Some(keypair),
+            ..Self::new()
+        }
+    }
+
+    /// Builds an index over every manifest in `store`, a page at a time.
+    pub fn load(store: &dyn CatalogStore) -> Result<Self> {
+        Self::new().fill(store)
+    }
+
+    /// Like `load`, opening private manifests sealed to `keypair`.
+    pub fn load_with_keypair(store: &dyn CatalogStore, keypair: Option<Arc<Keypair>>) -> Result<Self> {
+        match keypair {
+            Some(keypair) => Self::with_keypair(keypair).fill(store),
+            None => Self::load(store),
         }
     }
 
+    fn fill(mut self, store: &dyn CatalogStore) -> Result<Self> {
+        let query = ManifestQuery {
+            limit: Some(500),
+            ..Default::default()
+        };
+        for manifest in ManifestPager::new(store, query) {
+            self.insert(manifest?);
+        }
+        for retraction in store.list_signed_retractions(&ManifestQuery::default())? {
+            self.retract(retraction);
+        }
+        Ok(self)
+    }
+
     pub fn insert(&mut self, manifest: Manifest) {
         let cid = manifest.cid.clone();
-        self.cid_index.insert(cid.clone(), manifest);
-        // For simplicity, assume node_id from manifest data
-        // In real impl, parse manifest
-        let node_id = "node123".to_string(); // placeholder
-        self.node_index.entry(node_id).or_insert(Vec::new()).push(cid);
+        match manifest.document().map(|document| self.open(&cid, document)) {
+            Ok(document) => {
+                if self.retracted.get(&cid).map_or(false, |r| r.creator == document.creator) {
+                    debug!("Not indexing retracted manifest {}", cid);
+                    return;
+                }
+                self.node_index.entry(document.node_id.clone()).or_default().push(cid.clone());
+                for tag in &document.tags {
+                    self.tag_index.entry(tag.clone()).or_default().push(cid.clone());
+                }
+                if let Some(name) = &document.name {
+                    let key = (document.namespace().to_string(), name.clone());
+                    self.names.entry(key).or_default().push(cid.clone());
+                }
+                let supersedes = document.supersedes.clone();
+                self.documents.insert(cid.clone(), document);
+                if let Some(previous) = supersedes {
+                    self.successors.entry(previous.clone()).or_default().push(cid.clone());
+                    if self.superseded_by(&previous).len() > 1 {
+                        warn!("{} is superseded by more than one version: {:?}", previous, self.superseded_by(&previous));
+                    }
+                }
+            }
+            Err(e) => warn!("Indexing {} without metadata: {}", cid, e),
+        }
+        self.cid_index.insert(cid, manifest);
+    }
+
+    /// Removes a retracted manifest from the index and remembers the
+    /// retraction. Check its signature first. Ignored, returning false, if
+    /// the indexed manifest has another creator.
+    pub fn retract(&mut self, retraction: Retraction) -> bool {
+        let cid = retraction.cid.clone();
+        if self.cid_index.contains_key(&cid)
+            && self.documents.get(&cid).map(|d| d.creator.as_str()) != Some(retraction.creator.as_str())
+        {
+            warn!("Ignoring retraction of {} by {}: not its creator", cid, retraction.creator);
+            return false;
+        }
+        if let Some(document) = self.documents.remove(&cid) {
+            remove_cid(&mut self.node_index, &document.node_id, &cid);
+            for tag in &document.tags {
+                remove_cid(&mut self.tag_index, tag, &cid);
+            }
+            if let Some(name) = &document.name {
+                remove_cid(&mut self.names, &(document.namespace().to_string(), name.clone()), &cid);
+            }
+            if let Some(previous) = &document.supersedes {
+                remove_cid(&mut self.successors, previous, &cid);
+            }
+        }
+        self.cid_index.remove(&cid);
+        self.retracted.insert(cid, retraction);
+        true
+    }
+
+    /// Why `cid` is missing from the index, if its creator retracted it.
+    pub fn retraction(&self, cid: &str) -> Option<&Retraction> {
+        self.retracted.get(cid)
+    }
+
+    /// The readable form of `document`: opened if it is sealed to this
+    /// index's key, as-is otherwise.
+    fn open(&self, cid: &str, document: ManifestDocument) -> ManifestDocument {
+        let (Some(envelope), Some(keypair)) = (&document.encrypted, &self.keypair) else {
+            return document;
+        };
+        if !envelope.is_recipient(&keypair.pubkey()) {
+            debug!("Indexing private manifest {} by public fields only", cid);
+            return document;
+        }
+        match envelope::open(&document, keypair) {
+            Ok(opened) => opened,
+            Err(e) => {
+                warn!("Failed to open private manifest {}: {}", cid, e);
+                document
+            }
+        }
+    }
+
+    /// Whether `cid` is private and this index couldn't open it.
+    pub fn is_sealed(&self, cid: &str) -> bool {
+        self.documents.get(cid).map_or(false, |d| d.encrypted.is_some())
     }
 
     pub fn get_by_cid(&self, cid: &str) -> Option<&Manifest> {
         self.cid_index.get(cid)
     }
 
+    pub fn document(&self, cid: &str) -> Option<&ManifestDocument> {
+        self.documents.get(cid)
+    }
+
+    pub fn cids(&self) -> impl Iterator<Item = &String> {
+        self.cid_index.keys()
+    }
+
     pub fn get_by_node(&self, node_id: &str) -> Option<&Vec<String>> {
         self.node_index.get(node_id)
     }
 
+    pub fn get_by_tag(&self, tag: &str) -> Option<&Vec<String>> {
+        self.tag_index.get(tag)
+    }
+
+    /// `cid` followed by its ancestors, nearest first, following `parents`
+    /// through every document in the index. Ancestors not in the index are
+    /// included, but their own parents are unknown.
     pub fn lineage(&self, cid: &str) -> Vec<String> {
-        // Placeholder: return related cids
-        vec![cid.to_string()]
+        let mut lineage = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([cid.to_string()]);
+        while let Some(current) = queue.pop_front() {
+            if !seen.insert(current.clone()) {
+                continue;
+            }
+            if let Some(document) = self.documents.get(&current) {
+                queue.extend(document.parents.iter().cloned());
+            }
+            lineage.push(current);
+        }
+        lineage
+    }
+
+    /// Versions that supersede `cid`. Claims from manifests with another
+    /// creator or name are ignored, so nobody can supersede someone else's
+    /// manifest.
+    pub fn superseded_by(&self, cid: &str) -> Vec<&str> {
+        let Some(previous) = self.documents.get(cid) else { return Vec::new() };
+        self.successors
+            .get(cid)
+            .into_iter()
+            .flatten()
+            .filter(|next| {
+                self.documents
+                    .get(*next)
+                    .map_or(false, |d| d.creator == previous.creator && d.name == previous.name)
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Namespaces holding a manifest called `name`, sorted.
+    pub fn namespaces_named(&self, name: &str) -> Vec<&str> {
+        let mut namespaces: Vec<&str> = self
+            .names
+            .keys()
+            .filter(|(_, n)| n == name)
+            .map(|(namespace, _)| namespace.as_str())
+            .collect();
+        namespaces.sort();
+        namespaces
+    }
+
+    /// Creators that publish `name` in `namespace`, sorted. A name belongs
+    /// to its creator: in a shared namespace each creator's versions form
+    /// their own chain.
+    pub fn creators_named(&self, namespace: &str, name: &str) -> Vec<&str> {
+        let mut creators: Vec<&str> = self
+            .names
+            .get(&(namespace.to_string(), name.to_string()))
+            .into_iter()
+            .flatten()
+            .map(|cid| self.documents[cid].creator.as_str())
+            .collect();
+        creators.sort();
+        creators.dedup();
+        creators
+    }
+
+    /// The current version of `creator`'s `name` in `namespace`: of the
+    /// versions nothing supersedes, the one created last. Retracted
+    /// manifests leave the catalog, so the version a retracted one replaced
+    /// is current again.
+    pub fn latest(&self, namespace: &str, name: &str, creator: &str) -> Option<&str> {
+        self.names
+            .get(&(namespace.to_string(), name.to_string()))?
+            .iter()
+            .filter(|cid| self.documents[*cid].creator == creator && self.superseded_by(cid).is_empty())
+            .max_by_key(|cid| (self.documents[*cid].created_at, *cid))
+            .map(String::as_str)
+    }
+
+    /// The version chain ending at `latest`, newest first. Stops at the
+    /// first predecessor that isn't indexed.
+    pub fn versions(&self, namespace: &str, name: &str, creator: &str) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut current = self.latest(namespace, name, creator).map(str::to_string);
+        while let Some(cid) = current {
+            current = self
+                .documents
+                .get(&cid)
+                .and_then(|d| d.supersedes.clone())
+                .filter(|previous| self.superseded_by(previous).contains(&cid.as_str()));
+            chain.push(cid);
+        }
+        chain
+    }
+
+    /// Manifests that more than one version claims to supersede.
+    pub fn version_conflicts(&self) -> Vec<VersionConflict> {
+        let mut conflicts: Vec<VersionConflict> = self
+            .successors
+            .keys()
+            .filter_map(|cid| {
+                let mut claimants: Vec<String> = self.superseded_by(cid).into_iter().map(String::from).collect();
+                claimants.sort();
+                (claimants.len() > 1).then(|| VersionConflict { superseded: cid.clone(), claimants })
+            })
+            .collect();
+        conflicts.sort_by(|a, b| a.superseded.cmp(&b.superseded));
+        conflicts
+    }
+
+    /// CIDs in `cid`'s lineage that aren't in the index yet. Retracted ones
+    /// are gone for good and not listed.
+    pub fn unknown_references(&self, cid: &str) -> Vec<String> {
+        self.lineage(cid)
+            .into_iter()
+            .filter(|c| !self.cid_index.contains_key(c) && !self.retracted.contains_key(c))
+            .collect()
+    }
+}
+
+fn remove_cid<K: std::hash::Hash + Eq>(map: &mut HashMap<K, Vec<String>>, key: &K, cid: &str) {
+    if let Some(cids) = map.get_mut(key) {
+        cids.retain(|c| c != cid);
+        if cids.is_empty() {
+            map.remove(key);
+        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(cid: &str, parents: &[&str]) -> Manifest {
+        let document = serde_json::json!({
+            "schema_version": 1,
+            "creator": "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS",
+            "node_id": "node1",
+            "parents": parents,
+            "tags": ["llm"],
+            "created_at": 1,
+        });
+        Manifest { cid: cid.to_string(), data: document.to_string().into_bytes(), timestamp: 1 }
+    }
+
+    #[test]
+    fn test_lineage_follows_parents() {
+        let a = "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n";
+        let b = "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH";
+        let c = "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn";
+        let mut index = Index::new();
+        index.insert(manifest(c, &[b]));
+        index.insert(manifest(b, &[a]));
+
+        assert_eq!(index.lineage(c), vec![c.to_string(), b.to_string(), a.to_string()]);
+        assert_eq!(index.unknown_references(c), vec![a.to_string()]);
+        assert_eq!(index.get_by_node("node1").unwrap().len(), 2);
+        assert_eq!(index.get_by_tag("llm").unwrap().len(), 2);
+    }
+
+    fn version(cid: &str, creator: &str, supersedes: Option<&str>, created_at: i64) -> Manifest {
+        let mut document = serde_json::json!({
+            "schema_version": 1,
+            "name": "llama-ft",
+            "namespace": "lab",
+            "creator": creator,
+            "node_id": "node1",
+            "created_at": created_at,
+        });
+        if let Some(previous) = supersedes {
+            document["supersedes"] = previous.into();
+        }
+        Manifest { cid: cid.to_string(), data: document.to_string().into_bytes(), timestamp: 1 }
+    }
+
+    #[test]
+    fn test_version_chains() {
+        let creator = "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS";
+        let stranger = "11111111111111111111111111111111";
+        let v1 = "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n";
+        let v2 = "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH";
+        let fork = "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn";
+        let mut index = Index::new();
+        index.insert(version(v1, creator, None, 1));
+        index.insert(version(v2, creator, Some(v1), 2));
+        // Someone else can't take over the name
+        index.insert(version("QmHijack", stranger, Some(v2), 3));
+
+        assert_eq!(index.latest("lab", "llama-ft", creator), Some(v2));
+        assert_eq!(index.versions("lab", "llama-ft", creator), vec![v2.to_string(), v1.to_string()]);
+        assert_eq!(index.namespaces_named("llama-ft"), vec!["lab"]);
+        assert_eq!(index.creators_named("lab", "llama-ft"), vec![stranger, creator]);
+        assert!(index.version_conflicts().is_empty());
+
+        index.insert(version(fork, creator, Some(v1), 3));
+        assert_eq!(index.latest("lab", "llama-ft", creator), Some(fork));
+        assert_eq!(
+            index.version_conflicts(),
+            vec![VersionConflict { superseded: v1.to_string(), claimants: vec![fork.to_string(), v2.to_string()] }]
+        );
+    }
+
+    #[test]
+    fn test_retract_restores_previous_version() {
+        let creator = "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS";
+        let v1 = "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n";
+        let v2 = "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH";
+        let mut index = Index::new();
+        index.insert(version(v1, creator, None, 1));
+        index.insert(version(v2, creator, Some(v1), 2));
+        let retraction = |creator: &str| Retraction {
+            cid: v2.to_string(),
+            creator: creator.to_string(),
+            reason: "bad weights".to_string(),
+            retracted_at: 3,
+            signature: String::new(),
+        };
+
+        assert!(!index.retract(retraction("11111111111111111111111111111111")));
+        assert_eq!(index.latest("lab", "llama-ft", creator), Some(v2));
+        assert!(index.retract(retraction(creator)));
+        assert_eq!(index.latest("lab", "llama-ft", creator), Some(v1));
+        assert!(index.get_by_cid(v2).is_none() && index.retraction(v2).is_some());
+        // A late copy from a peer stays out
+        index.insert(version(v2, creator, Some(v1), 2));
+        assert!(index.get_by_cid(v2).is_none());
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::Result;
+
+use crate::manifest::{Manifest, ManifestDocument};
+use crate::retraction::RetractedScope;
+use crate::schema::SchemaRegistry;
+use crate::store::CatalogStore;
+
+/// What the offline ingest checks decided about a manifest.
+#[derive(Debug)]
+pub enum Admission {
+    /// Passed; the parsed document, for the anchor check that follows.
+    Admitted(ManifestDocument),
+    /// A local tombstone covers the CID.
+    Tombstoned,
+    /// Its creator retracted it; the retraction's tombstone is now applied.
+    Retracted,
+    /// Bad signature or schema; the reason is worth recording as a rejection.
+    Invalid(String),
+}
+
+/// The checks every manifest from outside this node passes before its
+/// anchor is looked up, whether it came from a peer or a CAR archive: no
+/// tombstone, a valid creator signature and schema, and no retraction by
+/// that creator. The content hash is the caller's job, since only it knows
+/// whether a mismatch is the sender's fault or the CID's.
+pub fn admit(store: &dyn CatalogStore, schemas: &SchemaRegistry, manifest: &Manifest) -> Result<Admission> {
+    if store.get_tombstone(&manifest.cid)?.is_some() {
+        return Ok(Admission::Tombstoned);
+    }
+    let document = match manifest.verify_authorship().and_then(|d| schemas.validate(&d).map(|_| d)) {
+        Ok(document) => document,
+        Err(e) => return Ok(Admission::Invalid(format!("invalid manifest: {}", e))),
+    };
+    // The retraction may have arrived first; only the creator's counts
+    let retractions = store.get_retractions(&manifest.cid)?;
+    if let Some(retraction) = retractions.iter().find(|r| r.creator == document.creator) {
+        // Recorded before the manifest was seen; now its scope is known
+        store.record_retraction(retraction, Some(&RetractedScope::of(&document)), None)?;
+        store.apply_tombstone(&retraction.tombstone(chrono::Utc::now().timestamp()))?;
+        return Ok(Admission::Retracted);
+    }
+    Ok(Admission::Admitted(document))
+}
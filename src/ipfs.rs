@@ -1,8 +1,25 @@
 use ipfs_api::IpfsClient;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use std::io::Cursor;
 use anyhow::Result;
 use tracing::info;
 
+/// Multihash prefix for a 32-byte sha2-256 digest.
+const SHA2_256_PREFIX: [u8; 2] = [0x12, 0x20];
+
+/// Computes the CIDv0 (`Qm...`) of a raw block.
+pub fn compute_cid(data: &[u8]) -> String {
+    let mut multihash = SHA2_256_PREFIX.to_vec();
+    multihash.extend_from_slice(&Sha256::digest(data));
+    bs58::encode(multihash).into_string()
+}
+
+/// Checks that `data` hashes to `cid`. Only CIDv0 is supported for now.
+pub fn verify_cid(cid: &str, data: &[u8]) -> bool {
+    cid.starts_with("Qm") && compute_cid(data) == cid
+}
+
 pub struct IpfsCache {
     client: IpfsClient,
 }
@@ -26,6 +43,12 @@ impl IpfsCache {
         Ok(())
     }
 
+    /// CIDs pinned on the local IPFS node.
+    pub async fn pinned(&self) -> Result<HashSet<String>> {
+        let res = self.client.pin_ls(None, None).await?;
+        Ok(res.keys.into_keys().collect())
+    }
+
     pub async fn add_data(&self, data: &[u8]) -> Result<String> {
         let cursor = Cursor::new(data);
         let res = self.client.add(cursor).await?;
@@ -38,4 +61,18 @@ impl IpfsCache {
         let data = self.client.cat(cid).await?;
         Ok(data.to_vec())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_cid() {
+        // sha2-256 of the empty block, as reported by `ipfs block put`
+        let cid = compute_cid(b"");
+        assert_eq!(cid, "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n");
+        assert!(verify_cid(&cid, b""));
+        assert!(!verify_cid(&cid, b"tampered"));
+    }
+}
@@ -0,0 +1,28 @@
+//! NeuroSwarm node library: catalog storage, manifest model, sync and
+//! anchoring. The `nsd` binary is a thin CLI over these modules; other tools
+//! can depend on the crate to read catalogs or speak the sync protocol.
+
+pub mod anchor;
+pub mod async_storage;
+pub mod backup;
+pub mod blobs;
+pub mod car;
+pub mod config;
+pub mod envelope;
+pub mod fsck;
+pub mod index;
+pub mod ingest;
+pub mod ipfs;
+pub mod manifest;
+pub mod migrations;
+pub mod network;
+pub mod provenance;
+pub mod publish;
+pub mod retention;
+pub mod retraction;
+pub mod schema;
+pub mod storage;
+pub mod store;
+pub mod sync;
+
+pub use manifest::{Manifest, ManifestDocument};
@@ -2,25 +2,24 @@ use clap::{Parser, Subcommand};
 use std::fs;
 use std::sync::Arc;
 use anyhow::Result;
+use tracing::info;
 use tracing_subscriber;
 
-mod config;
-mod network;
-mod storage;
-mod ipfs;
-mod index;
-mod sync;
-mod anchor;
-
-use config::Config;
-use network::{Network, NetworkConfig};
-use storage::Storage;
-use ipfs::IpfsCache;
-use index::Index;
-use sync::SyncEngine;
-use anchor::Anchor;
-use sync::Sync;
-use anchor::Anchor;
+use neuroswarm_node::{backup, car, envelope, fsck, ipfs, migrations, provenance, publish, retention, retraction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use neuroswarm_node::manifest::{ManifestDocument, ManifestError};
+use neuroswarm_node::config::Config;
+use neuroswarm_node::network::{Network, NetworkConfig};
+use neuroswarm_node::storage::{ManifestCursor, ManifestQuery, Storage};
+use neuroswarm_node::async_storage::AsyncStorage;
+use neuroswarm_node::store::CatalogStore;
+use neuroswarm_node::ipfs::IpfsCache;
+use neuroswarm_node::index::Index;
+use neuroswarm_node::schema::SchemaRegistry;
+use neuroswarm_node::sync::{SyncEngine, SyncProgress};
+use neuroswarm_node::anchor::Anchor;
+use neuroswarm_node::retraction::{Retraction, RetractionOutcome};
 
 #[derive(Parser)]
 #[command(name = "nsd")]
@@ -32,6 +31,10 @@ pub struct Args {
     #[arg(long)]
     pub mode: Option<String>,
 
+    /// Limit catalog and index commands to this namespace (repeatable)
+    #[arg(long = "namespace", global = true)]
+    pub namespaces: Vec<String>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -61,9 +64,85 @@ pub enum Commands {
 
 #[derive(Subcommand)]
 pub enum CatalogCommands {
-    List,
-    Prune { days: u32 },
+    List {
+        /// Only manifests newer than this unix timestamp
+        #[arg(long)]
+        since: Option<i64>,
+        /// Only manifests at or before this unix timestamp
+        #[arg(long)]
+        until: Option<i64>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Apply the retention policy from ns.conf now
+    Prune {
+        /// Override `retention.max_age_days`
+        days: Option<u32>,
+        /// Show what would be evicted without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Withdraw a manifest you published: sign a retraction, tombstone and
+    /// unpin it locally, anchor it (validator mode) and announce it to peers
+    Retract {
+        cid: String,
+        /// Why it is withdrawn, e.g. leaked data; shown to peers
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
     Stats,
+    Conflicts { cid: Option<String> },
+    Fetch { cid: String },
+    /// Upgrade catalog.db to the latest schema
+    Migrate {
+        #[arg(long)]
+        dry_run: bool,
+    },
+    SchemaVersion,
+    /// Write manifests, provenance and attestations to a CAR archive
+    Export {
+        output: std::path::PathBuf,
+        /// Export only this CID (repeatable)
+        #[arg(long = "cid")]
+        cids: Vec<String>,
+        /// Read CIDs to export from a file, one per line
+        #[arg(long)]
+        cid_file: Option<std::path::PathBuf>,
+        #[arg(long)]
+        since: Option<i64>,
+        #[arg(long)]
+        until: Option<i64>,
+    },
+    /// Load a CAR archive written by `export`, verifying every block
+    Import { input: std::path::PathBuf },
+    /// Online backup of catalog.db, safe while the daemon runs
+    Backup { path: std::path::PathBuf },
+    /// Replace catalog.db with a validated backup (stop the daemon first)
+    Restore { path: std::path::PathBuf },
+    /// Check integrity, content hashes and references
+    Fsck {
+        /// Delete bad manifests, orphaned rows and unreferenced blobs
+        #[arg(long)]
+        repair: bool,
+        /// After repairing, fetch deleted manifests again from peers
+        #[arg(long, requires = "repair")]
+        refetch: bool,
+    },
+    /// Check a manifest file the way peers will before publishing it
+    Validate { file: std::path::PathBuf },
+    /// Publish a manifest: add its artifacts to IPFS, sign, store, anchor
+    /// (validator mode) and announce it to peers
+    Add {
+        file: std::path::PathBuf,
+        /// Encrypt the content to this public key (repeatable); this node
+        /// can always read its own private manifests
+        #[arg(long = "recipient")]
+        recipients: Vec<Pubkey>,
+    },
+    /// Show why manifests were refused at ingest
+    Rejections { cid: Option<String> },
+    /// List namespaces with their manifest counts
+    Namespaces,
 }
 
 #[derive(Subcommand)]
@@ -78,11 +157,36 @@ pub enum IndexCommands {
     Search { query: Option<String>, tag: Option<String> },
     Lineage { cid: String },
     Confidence { cid: String },
+    /// Show the current version of a named manifest
+    Latest {
+        name: String,
+        /// Only this creator's versions
+        #[arg(long)]
+        creator: Option<String>,
+    },
+    /// Show a named manifest's versions, newest first, and any forks
+    Versions {
+        name: String,
+        #[arg(long)]
+        creator: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
 pub enum AnchorCommands {
-    Verify { cid: String, creator: String },
+    /// Check a manifest's anchor. `creator` defaults to the one named in
+    /// the catalog's copy of the manifest.
+    Verify { cid: String, creator: Option<String> },
+    /// Check whether a manifest's creator retracted it on-chain
+    Retraction { cid: String, creator: Option<String> },
+    /// Show provenance, served from the catalog cache while fresh
+    Provenance {
+        cid: String,
+        creator: Option<String>,
+        /// Drop the cached entry and query Solana
+        #[arg(long)]
+        refresh: bool,
+    },
 }
 
 #[tokio::main]
@@ -101,109 +205,696 @@ async fn main() -> Result<()> {
 
     let mode = config.node.mode.as_str();
 
+    let namespaces = (!args.namespaces.is_empty()).then(|| args.namespaces.clone());
+
     match args.command {
         Some(Commands::Start) => {
             info!("Starting NeuroSwarm node in {} mode", mode);
 
             let network_config = NetworkConfig {
-                dns_seeds: config.network.dns_seeds,
-                static_peers: config.network.static_peers,
-                listen_addr: config.network.listen_addr,
+                dns_seeds: config.network.dns_seeds.clone(),
+                static_peers: config.network.static_peers.clone(),
+                listen_addr: config.network.listen_addr.clone(),
                 max_peers: config.network.max_peers,
+                identity_dir: Some(config.network.identity_dir.clone().into()),
+                insecure_skip_verify: config.network.insecure_skip_verify,
             };
 
             // Always start network for connectivity
-            let network = Arc::new(Network::new(network_config).await?);
-            let storage = Arc::new(Storage::new("catalog.db")?);
+            let network = Network::new(network_config).await?;
+            info!("Node id: {}", network.node_id());
+            let storage = match config.storage.backend.as_str() {
+                "memory" => {
+                    info!("Using in-memory catalog; nothing is persisted");
+                    AsyncStorage::in_memory()
+                }
+                _ => AsyncStorage::open("catalog.db")?,
+            };
+            let pins = node_pins(mode).map(Arc::new);
+            retention::spawn_janitor(storage.clone(), pins.clone(), config.retention.clone(), config.storage.clone());
+            if config.backup.enabled && config.storage.backend != "memory" {
+                backup::spawn_scheduled("catalog.db".into(), config.backup.clone());
+                info!("Scheduled catalog backups every {}h to {}", config.backup.interval_hours, config.backup.dir);
+            }
 
             // Load index for all modes that need it
-            let mut index = Index::new();
-            for manifest in storage.list_manifests()? {
-                index.insert(manifest);
-            }
+            let keypair = node_keypair(&config)?.map(Arc::new);
+            let index = storage.call(move |store| Index::load_with_keypair(store, keypair)).await?;
+            let schemas = Arc::new(SchemaRegistry::load(&config.schemas)?);
 
             match mode {
                 "validator" => {
                     // Validator: network, storage, anchor, sync
                     let anchor = Arc::new(Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?);
-                    let sync_engine = SyncEngine::new(storage.clone(), network.clone(), anchor.clone());
-                    network.start().await?;
-                    sync_engine.start_sync().await?;
+                    let sync_engine = SyncEngine::new(
+                        storage.clone(),
+                        network.clone(),
+                        anchor.clone(),
+                        config.provenance_cache.clone(),
+                        schemas.clone(),
+                        config.replicate.clone(),
+                        config.namespaces.clone(),
+                    )
+                    .await;
                     info!("Validator mode: anchoring and consensus active");
+                    // Peers get answers while our own catalog requests go out
+                    tokio::try_join!(
+                        network.start(),
+                        network.connect_bootstrap_peers(),
+                        sync_engine.serve(),
+                        sync_engine.run_sync(),
+                    )?;
                 }
                 "gateway" => {
                     // Gateway: network, storage, API server (future: start HTTP server)
-                    network.start().await?;
+                    let anchor = Arc::new(Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?);
+                    let sync_engine = SyncEngine::new(
+                        storage.clone(),
+                        network.clone(),
+                        anchor.clone(),
+                        config.provenance_cache.clone(),
+                        schemas.clone(),
+                        config.replicate.clone(),
+                        config.namespaces.clone(),
+                    )
+                    .await;
                     info!("Gateway mode: API server active");
+                    // Answers peers for the served namespaces; no catalog of its own to pull
+                    tokio::try_join!(network.start(), network.connect_bootstrap_peers(), sync_engine.serve())?;
                     // TODO: Start HTTP server for API endpoints
                 }
                 "indexer" => {
                     // Indexer: network, storage, index, search APIs (future: start search server)
-                    network.start().await?;
+                    let anchor = Arc::new(Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?);
+                    let sync_engine = SyncEngine::new(
+                        storage.clone(),
+                        network.clone(),
+                        anchor.clone(),
+                        config.provenance_cache.clone(),
+                        schemas.clone(),
+                        config.replicate.clone(),
+                        config.namespaces.clone(),
+                    )
+                    .await;
                     info!("Indexer mode: search and lineage active");
+                    // Answers peers for the served namespaces; no catalog of its own to pull
+                    tokio::try_join!(network.start(), network.connect_bootstrap_peers(), sync_engine.serve())?;
                     // TODO: Start search API server
                 }
                 "full" | _ => {
                     // Full node: all components
                     let anchor = Arc::new(Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?);
-                    let ipfs = IpfsCache::new();
-                    let sync_engine = SyncEngine::new(storage.clone(), network.clone(), anchor.clone());
-                    network.start().await?;
-                    sync_engine.start_sync().await?;
+                    let mut sync_engine = SyncEngine::new(
+                        storage.clone(),
+                        network.clone(),
+                        anchor.clone(),
+                        config.provenance_cache.clone(),
+                        schemas.clone(),
+                        config.replicate.clone(),
+                        config.namespaces.clone(),
+                    )
+                    .await;
+                    if let Some(ipfs) = pins.filter(|_| config.storage.unpin_retracted) {
+                        sync_engine = sync_engine.with_pins(ipfs);
+                    }
                     info!("Full mode: all components active");
+                    // Peers get answers while our own catalog requests go out
+                    tokio::try_join!(
+                        network.start(),
+                        network.connect_bootstrap_peers(),
+                        sync_engine.serve(),
+                        sync_engine.run_sync(),
+                    )?;
                 }
             }
         }
-        Some(Commands::Catalog { catalog_cmd }) => {
+        Some(Commands::Status) => {
             let storage = Storage::new("catalog.db")?;
-            match catalog_cmd {
-                CatalogCommands::List => {
-                    let manifests = storage.list_manifests()?;
-                    for m in manifests {
-                        println!("CID: {}", m.cid);
+            let progress = SyncProgress::from_states(storage.list_sync_state()?);
+            println!("Node Status:");
+            println!("- Version: {}", config.node.version);
+            println!("- Mode: {}", mode);
+            println!("- Peers: {} synced", progress.synced());
+            match progress.percent() {
+                Some(percent) => println!("- Sync Progress: {:.0}%", percent),
+                None => println!("- Sync Progress: unknown"),
+            }
+            println!("- Pending Verification: {}", progress.pending_verification());
+            println!("- Remaining (est.): {}", progress.remaining());
+        }
+        Some(Commands::Catalog { catalog_cmd }) => match catalog_cmd {
+            // The only command that opens the catalog without migrating it
+            CatalogCommands::Migrate { dry_run: true } => {
+                let catalog = std::path::Path::new("catalog.db");
+                if !catalog.exists() {
+                    anyhow::bail!("{} does not exist; it is created at the latest schema on first open", catalog.display());
+                }
+                let conn = rusqlite::Connection::open_with_flags(catalog, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+                let from = migrations::current_version(&conn)?;
+                let steps = migrations::migrate(&conn, true)?;
+                if steps.is_empty() {
+                    println!("Catalog schema is up to date (v{})", from);
+                }
+                for step in steps {
+                    println!("Would apply v{}: {}", step.version, step.description);
+                }
+                // Payloads written before the blob store existed are still inline
+                let has_manifests: bool = conn.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'manifests')",
+                    [],
+                    |row| row.get(0),
+                )?;
+                let inline: u64 = if has_manifests {
+                    conn.query_row("SELECT COUNT(*) FROM manifests WHERE data IS NOT NULL", [], |row| row.get(0))?
+                } else {
+                    0
+                };
+                if inline > 0 {
+                    println!("Would move {} inline payloads to the blob store", inline);
+                }
+            }
+            CatalogCommands::Migrate { dry_run: false } => {
+                let conn = rusqlite::Connection::open("catalog.db")?;
+                let from = migrations::current_version(&conn)?;
+                let steps = migrations::migrate(&conn, false)?;
+                if steps.is_empty() {
+                    println!("Catalog schema is up to date (v{})", from);
+                }
+                for step in steps {
+                    println!("Applied v{}: {}", step.version, step.description);
+                }
+                let moved = Storage::new("catalog.db")?.externalize_blobs()?;
+                if moved > 0 {
+                    println!("Moved {} inline payloads to the blob store", moved);
+                }
+            }
+            CatalogCommands::SchemaVersion => {
+                let storage = Storage::new("catalog.db")?;
+                println!("Schema version: {} (latest: {})", storage.schema_version()?, migrations::latest_version());
+            }
+            CatalogCommands::Backup { path } => {
+                let catalog = std::path::Path::new("catalog.db");
+                migrate_existing(catalog)?;
+                let report = backup::backup(catalog, &path)?;
+                println!(
+                    "Backed up catalog (schema v{}, {} blobs) to {}",
+                    report.schema_version,
+                    report.blobs_copied,
+                    path.display()
+                );
+            }
+            CatalogCommands::Restore { path } => {
+                let catalog = std::path::Path::new("catalog.db");
+                let version = backup::restore(&path, catalog)?;
+                println!("Restored catalog.db from {} (schema v{})", path.display(), version);
+                if version < migrations::latest_version() {
+                    migrate_existing(catalog)?;
+                    println!("Migrated catalog.db to schema v{}", migrations::latest_version());
+                }
+            }
+            CatalogCommands::Fsck { repair, refetch } => {
+                let catalog = std::path::Path::new("catalog.db");
+                migrate_existing(catalog)?;
+                let report = fsck::check(catalog)?;
+                for error in &report.integrity_errors {
+                    println!("integrity: {}", error);
+                }
+                for (cid, problem) in &report.bad_manifests {
+                    println!("manifest {}: {}", cid, problem);
+                }
+                for cid in &report.orphaned_provenance {
+                    println!("orphaned provenance: {}", cid);
+                }
+                for (id, cid) in &report.orphaned_attestations {
+                    println!("orphaned attestation {} for {}", id, cid);
+                }
+                for cid in &report.orphaned_tags {
+                    println!("orphaned tags for {}", cid);
+                }
+                for key in &report.orphaned_blobs {
+                    println!("unreferenced blob: {}", key);
+                }
+                if report.is_clean() {
+                    println!("Catalog is clean");
+                    return Ok(());
+                }
+                if !repair {
+                    anyhow::bail!("found {} problems; rerun with --repair to fix them", report.problem_count());
+                }
+                let removed = fsck::repair(catalog, &report)?;
+                println!("Repaired {} problems", report.problem_count() - report.integrity_errors.len());
+                if refetch && !removed.is_empty() {
+                    let sync_engine = connect_swarm(&config, AsyncStorage::open(catalog)?).await?;
+                    for cid in &removed {
+                        match sync_engine.fetch_manifest(cid).await? {
+                            Some(_) => println!("Refetched {}", cid),
+                            None => println!("No peer could supply {}", cid),
+                        }
+                    }
+                }
+                if !report.integrity_errors.is_empty() {
+                    anyhow::bail!("SQLite integrity check failed; restore from a backup with `nsd catalog restore`");
+                }
+            }
+            CatalogCommands::Validate { file } => {
+                let data = fs::read(&file)?;
+                let document = ManifestDocument::parse(&data)?;
+                let schemas = SchemaRegistry::load(&config.schemas)?;
+                schemas.validate(&document)?;
+                println!("{} is a valid {} manifest", file.display(), document.kind.as_deref().unwrap_or("untyped"));
+                println!("CID: {}", ipfs::compute_cid(&document.to_bytes()));
+                if let Some(envelope) = &document.encrypted {
+                    println!("Private: content sealed to {} recipients and not schema-checked", envelope.recipients.len());
+                }
+                // Peers also reject these, but publishing canonicalizes and signs
+                if document.to_bytes() != data {
+                    println!("warning: not canonically encoded; publish the canonical form");
+                }
+                match document.verify_signature() {
+                    Ok(()) => println!("Signed by {}", document.creator),
+                    Err(ManifestError::Unsigned) => println!("warning: unsigned; peers reject unsigned manifests"),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            CatalogCommands::Add { file, recipients } => {
+                let keypair = node_keypair(&config)?
+                    .ok_or_else(|| anyhow::anyhow!("set node.keypair in ns.conf to publish manifests"))?;
+                let mut draft: serde_json::Value = serde_json::from_slice(&fs::read(&file)?)?;
+                let base_dir = file.parent().unwrap_or(std::path::Path::new("."));
+
+                let schemas = SchemaRegistry::load(&config.schemas)?;
+                let storage = AsyncStorage::open("catalog.db")?;
+
+                let ipfs = IpfsCache::new();
+                let added = publish::add_artifacts(&mut draft, base_dir, &ipfs, !recipients.is_empty()).await?;
+                for artifact in &added {
+                    println!("[artifacts] added and pinned {} as {} ({} bytes)", artifact.name, artifact.cid, artifact.size);
+                }
+                // Validation needs the artifact CIDs, so it runs after pinning;
+                // nothing references the new pins if it fails
+                let checked = async {
+                    let (document, manifest) =
+                        publish::finish(draft, &keypair, &config.node.node_id, chrono::Utc::now().timestamp(), &recipients)?;
+                    // The registry can't see into a sealed document; check what went in
+                    let readable = match &document.encrypted {
+                        Some(_) => envelope::open(&document, &keypair)?,
+                        None => document.clone(),
+                    };
+                    schemas.validate(&readable)?;
+                    if storage.get_tombstone(&manifest.cid).await?.is_some() {
+                        anyhow::bail!("{} is tombstoned in the local catalog", manifest.cid);
                     }
+                    Ok::<_, anyhow::Error>((document, manifest))
                 }
-                CatalogCommands::Prune { days } => {
-                    let before = chrono::Utc::now().timestamp() - (days as i64 * 86400);
-                    let count = storage.prune_old(before)?;
-                    println!("Pruned {} manifests", count);
+                .await;
+                let (document, manifest) = match checked {
+                    Ok(checked) => checked,
+                    Err(e) => {
+                        for artifact in added.iter().filter(|a| a.newly_pinned) {
+                            match ipfs.unpin(&artifact.cid).await {
+                                Ok(()) => println!("[artifacts] unpinned {}", artifact.cid),
+                                Err(unpin) => println!("[artifacts] unpinning {} failed: {}", artifact.cid, unpin),
+                            }
+                        }
+                        return Err(e);
+                    }
+                };
+                println!("[validate] ok: {} manifest signed by {}", document.kind.as_deref().unwrap_or("untyped"), document.creator);
+                if let Some(envelope) = &document.encrypted {
+                    println!("[seal] encrypted to {} recipients", envelope.recipients.len());
                 }
-                CatalogCommands::Stats => {
-                    let (m_count, a_count) = storage.stats()?;
-                    println!("Manifests: {}, Attestations: {}", m_count, a_count);
+                println!("[cid] {}", manifest.cid);
+
+                let outcome = storage.merge_manifest(&manifest, "local", None).await?;
+                println!("[store] {:?}", outcome);
+
+                if mode == "validator" {
+                    let anchor = Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?;
+                    match anchor.anchor_manifest(&manifest.cid, &keypair).await {
+                        Ok(signature) => println!("[anchor] tx {}", signature),
+                        Err(e) => println!("[anchor] failed: {}", e),
+                    }
+                } else {
+                    println!("[anchor] skipped: only validator nodes submit anchors");
                 }
+
+                // Announce last, so peers can find the anchor when they verify
+                let sync_engine = connect_swarm(&config, storage).await?;
+                println!("[announce] sent to {} peers", sync_engine.announce(&manifest).await);
             }
-        }
+            CatalogCommands::Retract { cid, reason } => {
+                let keypair = node_keypair(&config)?
+                    .ok_or_else(|| anyhow::anyhow!("set node.keypair in ns.conf to retract manifests"))?;
+                let storage = AsyncStorage::open("catalog.db")?;
+                let Some(manifest) = storage.get_manifest(&cid).await? else {
+                    anyhow::bail!("{} is not in the local catalog", cid);
+                };
+                let creator = manifest.document()?.creator;
+                if creator != keypair.pubkey().to_string() {
+                    anyhow::bail!("{} was published by {}; only its creator can retract it", cid, creator);
+                }
+                let record = Retraction::new(&cid, &reason, &keypair, chrono::Utc::now().timestamp());
+                let applied = record.clone();
+                match storage.call(move |store| retraction::apply(store, &applied, None)).await? {
+                    RetractionOutcome::Applied(_) => println!("[store] tombstoned {}", cid),
+                    outcome => anyhow::bail!("{} was not retracted: {:?}", cid, outcome),
+                }
+
+                if let Some(ipfs) = node_pins(mode).filter(|_| config.storage.unpin_retracted) {
+                    for pinned in retraction::pinned_cids(&manifest, Some(&keypair)) {
+                        match ipfs.unpin(&pinned).await {
+                            Ok(()) => println!("[unpin] {}", pinned),
+                            Err(e) => println!("[unpin] {} failed: {}", pinned, e),
+                        }
+                    }
+                }
+
+                if mode == "validator" {
+                    let anchor = Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?;
+                    match anchor.anchor_retraction(&cid, &keypair).await {
+                        Ok(signature) => println!("[anchor] tx {}", signature),
+                        Err(e) => println!("[anchor] failed: {}", e),
+                    }
+                } else {
+                    println!("[anchor] skipped: only validator nodes submit anchors");
+                }
+
+                let sync_engine = connect_swarm(&config, storage).await?;
+                println!("[announce] sent to {} peers", sync_engine.announce_retraction(&record).await);
+            }
+            CatalogCommands::List { since, until, limit } => {
+                let storage = Storage::new("catalog.db")?;
+                let mut query = ManifestQuery { since, until, namespaces: namespaces.clone(), ..Default::default() };
+                let mut remaining = limit.unwrap_or(usize::MAX);
+                while remaining > 0 {
+                    query.limit = Some(remaining.min(500));
+                    let page = storage.query_manifest_summaries(&query)?;
+                    let Some(last) = page.last() else { break };
+                    query.after = Some(ManifestCursor { timestamp: last.timestamp, cid: last.cid.clone() });
+                    remaining -= page.len();
+                    for m in page {
+                        println!("CID: {} namespace: {} timestamp: {} size: {}", m.cid, m.namespace, m.timestamp, m.size);
+                    }
+                }
+                println!("Total: {}", storage.count_manifests(&ManifestQuery { since, until, namespaces, ..Default::default() })?);
+            }
+            CatalogCommands::Prune { days, dry_run } => {
+                let mut policy = config.retention.clone();
+                policy.max_age_days = days.or(policy.max_age_days);
+                if !retention::has_limits(&policy) {
+                    println!("No retention limits configured; set retention.max_age_days or max_catalog_mb, or pass <days>");
+                }
+                let pins = node_pins(mode);
+                let storage = AsyncStorage::open("catalog.db")?;
+                let plan = retention::run(&storage, pins.as_ref(), &policy, &config.storage, dry_run).await?;
+                let verb = if dry_run { "Would evict" } else { "Evicted" };
+                for eviction in &plan.evict {
+                    let m = &eviction.manifest;
+                    println!("{} {} ({:?}, timestamp {}, {} bytes)", verb, m.cid, eviction.reason, m.timestamp, m.size);
+                }
+                for (m, protection) in &plan.protected {
+                    println!("Kept {} ({:?})", m.cid, protection);
+                }
+                println!(
+                    "{} {} manifests, {} of {} bytes",
+                    verb,
+                    plan.evict.len(),
+                    plan.freed_bytes(),
+                    plan.total_bytes
+                );
+            }
+            CatalogCommands::Stats => {
+                let storage = Storage::new("catalog.db")?;
+                let (m_count, a_count) = storage.stats()?;
+                println!("Manifests: {}, Attestations: {}", m_count, a_count);
+            }
+            CatalogCommands::Conflicts { cid } => {
+                let storage = Storage::new("catalog.db")?;
+                for c in storage.list_conflicts(cid.as_deref())? {
+                    println!(
+                        "{} {:?} from {}: stored ts {}, incoming ts {} (detected {})",
+                        c.cid, c.kind, c.source, c.existing_timestamp, c.incoming_timestamp, c.detected_at
+                    );
+                }
+            }
+            CatalogCommands::Namespaces => {
+                let storage = Storage::new("catalog.db")?;
+                for (namespace, count) in storage.list_namespaces()? {
+                    println!("{} {}", namespace, count);
+                }
+            }
+            CatalogCommands::Rejections { cid } => {
+                let storage = Storage::new("catalog.db")?;
+                for r in storage.list_rejections(cid.as_deref())? {
+                    println!("{} from {} at {}: {}", r.cid, r.source, r.rejected_at, r.reason);
+                }
+            }
+            CatalogCommands::Export { output, mut cids, cid_file, since, until } => {
+                let storage = Storage::new("catalog.db")?;
+                if let Some(path) = cid_file {
+                    let list = fs::read_to_string(path)?;
+                    cids.extend(list.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from));
+                }
+                let filter = car::ExportFilter { cids, since, until, namespaces };
+                let file = std::io::BufWriter::new(fs::File::create(&output)?);
+                let count = car::export_catalog(&storage, file, &filter)?;
+                println!("Exported {} manifests to {}", count, output.display());
+            }
+            CatalogCommands::Import { input } => {
+                let storage = Storage::new("catalog.db")?;
+                let file = std::io::BufReader::new(fs::File::open(&input)?);
+                let source = format!("car:{}", input.display());
+                let schemas = SchemaRegistry::load(&config.schemas)?;
+                let anchor = Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?;
+                // The import runs synchronously against the catalog; anchors are checked inline
+                let mut anchored = |cid: &str, creator: &str| {
+                    tokio::task::block_in_place(|| {
+                        tokio::runtime::Handle::current().block_on(anchor.verify_manifest(cid, creator))
+                    })
+                };
+                let summary = car::import_catalog(&storage, file, &source, &schemas, &mut anchored)?;
+                println!(
+                    "Imported {} manifests ({} unchanged, {} conflicts, {} tombstoned, {} rejected, {} attestations rejected)",
+                    summary.inserted,
+                    summary.unchanged,
+                    summary.conflicts,
+                    summary.skipped_tombstoned,
+                    summary.rejected,
+                    summary.rejected_attestations
+                );
+            }
+            CatalogCommands::Fetch { cid } => {
+                let sync_engine = connect_swarm(&config, AsyncStorage::open("catalog.db")?).await?;
+                match sync_engine.fetch_manifest(&cid).await? {
+                    Some(m) => println!("Fetched {} ({} bytes, timestamp {})", m.cid, m.data.len(), m.timestamp),
+                    None => println!("No peer could supply {}", cid),
+                }
+            }
+        },
         Some(Commands::Index { index_cmd }) => {
-            let index = Index::new();
             // Load from storage
             let storage = Storage::new("catalog.db")?;
-            for manifest in storage.list_manifests()? {
-                index.insert(manifest);
-            }
+            let mut index = Index::load_with_keypair(&storage, node_keypair(&config)?.map(Arc::new))?;
             match index_cmd {
                 IndexCommands::Search { query, tag } => {
-                    // Mock search
-                    println!("Search results for query: {:?}, tag: {:?}", query, tag);
+                    let mut cids: Vec<&String> = match &tag {
+                        Some(tag) => index.get_by_tag(tag).map(|cids| cids.iter().collect()).unwrap_or_default(),
+                        None => index.cids().collect(),
+                    };
+                    if let Some(namespaces) = &namespaces {
+                        cids.retain(|cid| {
+                            index.document(cid).map_or(false, |d| namespaces.iter().any(|n| n == d.namespace()))
+                        });
+                    }
+                    if let Some(query) = &query {
+                        let query = query.to_lowercase();
+                        cids.retain(|cid| {
+                            cid.to_lowercase().contains(&query)
+                                || index.document(cid).map_or(false, |d| {
+                                    d.node_id.to_lowercase().contains(&query)
+                                        || d.tags.iter().any(|t| t.to_lowercase().contains(&query))
+                                        || d.artifacts.iter().any(|a| a.name.to_lowercase().contains(&query))
+                                })
+                        });
+                    }
+                    cids.sort();
+                    for cid in cids {
+                        match index.document(cid) {
+                            Some(_) if index.is_sealed(cid) => println!("{} (private)", cid),
+                            Some(d) => println!(
+                                "{} namespace={} node={} tags={:?} parents={}",
+                                cid,
+                                d.namespace(),
+                                d.node_id,
+                                d.tags,
+                                d.parents.len()
+                            ),
+                            None => println!("{} (unparsed)", cid),
+                        }
+                    }
                 }
                 IndexCommands::Lineage { cid } => {
-                    println!("Lineage for {}: Mock lineage data", cid);
+                    if !index.unknown_references(&cid).is_empty() {
+                        let sync_engine = connect_swarm(&config, AsyncStorage::spawn(storage)).await?;
+                        let fetched = sync_engine.fill_lineage(&mut index, &cid).await?;
+                        println!("Fetched {} missing manifests from peers", fetched);
+                    }
+                    let lineage: Vec<String> = index
+                        .lineage(&cid)
+                        .into_iter()
+                        .map(|c| match index.retraction(&c) {
+                            Some(r) => format!("{} (retracted: {})", c, r.reason),
+                            None => c,
+                        })
+                        .collect();
+                    println!("Lineage for {}: {:?}", cid, lineage);
                 }
                 IndexCommands::Confidence { cid } => {
                     println!("Confidence for {}: Mock confidence score", cid);
                 }
+                IndexCommands::Latest { name, creator } => {
+                    for (namespace, creator) in named_versions(&index, &name, namespaces.as_deref(), creator.as_deref()) {
+                        if let Some(cid) = index.latest(&namespace, &name, &creator) {
+                            println!("{} namespace={} creator={}", cid, namespace, creator);
+                        }
+                    }
+                }
+                IndexCommands::Versions { name, creator } => {
+                    let conflicts = index.version_conflicts();
+                    for (namespace, creator) in named_versions(&index, &name, namespaces.as_deref(), creator.as_deref()) {
+                        println!("{} in {} by {}:", name, namespace, creator);
+                        for (i, cid) in index.versions(&namespace, &name, &creator).iter().enumerate() {
+                            println!("  {}{}", cid, if i == 0 { " (latest)" } else { "" });
+                            for conflict in conflicts.iter().filter(|c| &c.superseded == cid) {
+                                println!("    fork: superseded by {}", conflict.claimants.join(", "));
+                            }
+                        }
+                    }
+                }
             }
         }
         Some(Commands::Anchor { anchor_cmd }) => {
-            let anchor = Anchor::new("https://api.devnet.solana.com", "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS")?;
+            let anchor = Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?;
             match anchor_cmd {
                 AnchorCommands::Verify { cid, creator } => {
+                    let creator = resolve_creator(&cid, creator)?;
                     let verified = anchor.verify_manifest(&cid, &creator).await?;
                     println!("Verification result for {}: {}", cid, verified);
                 }
+                AnchorCommands::Retraction { cid, creator } => {
+                    let creator = resolve_creator(&cid, creator)?;
+                    let retracted = anchor.verify_retraction(&cid, &creator).await?;
+                    println!("Retracted on-chain by {}: {}", creator, retracted);
+                }
+                AnchorCommands::Provenance { cid, creator, refresh } => {
+                    let creator = resolve_creator(&cid, creator)?;
+                    let cache = provenance::ProvenanceCache::new(
+                        Arc::new(anchor),
+                        AsyncStorage::open("catalog.db")?,
+                        config.provenance_cache.clone(),
+                    );
+                    if refresh && cache.invalidate(&cid).await? {
+                        println!("Invalidated cached provenance for {}", cid);
+                    }
+                    match cache.get(&cid, &creator).await? {
+                        Some(p) => println!(
+                            "{}: finalized {}, {} attestations, tx {}, slot {}",
+                            cid, p.finalized, p.attestation_count, p.tx_signature, p.slot
+                        ),
+                        None => println!("{} is not anchored on-chain", cid),
+                    }
+                    let stats = cache.stats();
+                    println!("(cache {})", if stats.hits > 0 { "hit" } else { "miss" });
+                }
             }
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// The IPFS node whose pins retention and retractions respect or release.
+/// Only full nodes run IPFS; in other modes there are no pins to consider.
+fn node_pins(mode: &str) -> Option<IpfsCache> {
+    (!matches!(mode, "validator" | "gateway" | "indexer")).then(IpfsCache::new)
+}
+
+/// Brings an existing catalog up to the latest schema, for the commands that
+/// work on the file rather than through `Storage`.
+fn migrate_existing(catalog: &std::path::Path) -> Result<()> {
+    if !catalog.exists() {
+        anyhow::bail!("{} does not exist", catalog.display());
+    }
+    Storage::new(catalog).map(drop)
+}
+
+/// The explicit `creator`, or the one recorded in the catalog's copy of
+/// `cid`.
+fn resolve_creator(cid: &str, creator: Option<String>) -> Result<String> {
+    if let Some(creator) = creator {
+        return Ok(creator);
+    }
+    let storage = Storage::new("catalog.db")?;
+    let Some(manifest) = storage.get_manifest(cid)? else {
+        // A retracted manifest is gone, but its retraction names the creator
+        if let Some(retraction) = storage.get_retractions(cid)?.into_iter().next() {
+            return Ok(retraction.creator);
+        }
+        anyhow::bail!("{} is not in the catalog; pass the creator explicitly", cid);
+    };
+    Ok(manifest.document()?.creator)
+}
+
+/// `(namespace, creator)` pairs that publish `name`, limited to the given
+/// namespaces and creator when set.
+fn named_versions(index: &Index, name: &str, namespaces: Option<&[String]>, creator: Option<&str>) -> Vec<(String, String)> {
+    let candidates: Vec<String> = match namespaces {
+        Some(namespaces) => namespaces.to_vec(),
+        None => index.namespaces_named(name).into_iter().map(String::from).collect(),
+    };
+    let mut pairs = Vec::new();
+    for namespace in candidates {
+        for found in index.creators_named(&namespace, name) {
+            if creator.map_or(true, |c| c == found) {
+                pairs.push((namespace.clone(), found.to_string()));
+            }
+        }
+    }
+    pairs
+}
+
+/// The key from `node.keypair`, if one is configured.
+fn node_keypair(config: &Config) -> Result<Option<Keypair>> {
+    let Some(path) = config.node.keypair.as_deref() else { return Ok(None) };
+    let keypair = read_keypair_file(path).map_err(|e| anyhow::anyhow!("reading keypair {}: {}", path, e))?;
+    Ok(Some(keypair))
+}
+
+/// Dials bootstrap peers and builds a `SyncEngine` for one-shot CLI commands
+/// that need data from the swarm.
+async fn connect_swarm(config: &Config, storage: AsyncStorage) -> Result<SyncEngine> {
+    let network = Network::new(NetworkConfig {
+        dns_seeds: config.network.dns_seeds.clone(),
+        static_peers: config.network.static_peers.clone(),
+        listen_addr: config.network.listen_addr.clone(),
+        max_peers: config.network.max_peers,
+        identity_dir: Some(config.network.identity_dir.clone().into()),
+        insecure_skip_verify: config.network.insecure_skip_verify,
+    })
+    .await?;
+    network.connect_bootstrap_peers().await?;
+    let peers = network.wait_for_peers(std::time::Duration::from_secs(10)).await;
+    info!("Connected to {} peers", peers);
+    let anchor = Arc::new(Anchor::new(&config.solana.rpc_url, &config.solana.program_id)?);
+    let schemas = Arc::new(SchemaRegistry::load(&config.schemas)?);
+    Ok(SyncEngine::new(
+        storage,
+        network,
+        anchor,
+        config.provenance_cache.clone(),
+        schemas,
+        config.replicate.clone(),
+        config.namespaces.clone(),
+    )
+    .await)
+}
@@ -0,0 +1,373 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+
+use crate::envelope::Envelope;
+
+/// Newest manifest schema this build writes. Documents with a higher
+/// version are still read; fields this build doesn't know are kept in
+/// `extra` so they survive a round trip.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+/// Namespace of manifests whose data doesn't parse, such as rows from
+/// catalogs that predate typed documents.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+const MAX_TAGS: usize = 64;
+const MAX_TAG_LEN: usize = 64;
+const MAX_NAME_LEN: usize = 128;
+
+/// A manifest as the catalog stores it and peers exchange it.
+///
+/// `cid` and `data` are the content: `data` hashes to `cid`, never changes
+/// and is identical on every node. `timestamp` is catalog metadata owned by
+/// whichever catalog holds the row; in a `CatalogChunk` it is the sender's
+/// value, which the receiver only uses as input to its merge rules. Other
+/// per-catalog metadata (provenance, attestations) travels in
+/// its own messages rather than on this type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub cid: String,
+    pub data: Vec<u8>,
+    pub timestamp: i64,
+}
+
+impl Manifest {
+    /// Parses `data` as a typed manifest document.
+    pub fn document(&self) -> Result<ManifestDocument, ManifestError> {
+        ManifestDocument::parse(&self.data)
+    }
+
+    /// Parses `data` and checks it is canonically encoded and signed by its
+    /// creator. Needs no network access, so sync runs it before any anchor
+    /// lookup.
+    pub fn verify_authorship(&self) -> Result<ManifestDocument, ManifestError> {
+        let document = self.document()?;
+        if document.to_bytes() != self.data {
+            return Err(ManifestError::NotCanonical);
+        }
+        document.verify_signature()?;
+        Ok(document)
+    }
+
+    /// The catalog namespace this manifest belongs to.
+    pub fn namespace(&self) -> String {
+        self.document()
+            .map(|document| document.namespace().to_string())
+            .unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string())
+    }
+
+    /// Whether `data` hashes to `cid`.
+    pub fn verify_content(&self) -> bool {
+        crate::ipfs::verify_cid(&self.cid, &self.data)
+    }
+}
+
+/// The typed content of `Manifest::data`: a JSON document describing one
+/// published model, dataset or other artifact set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestDocument {
+    pub schema_version: u32,
+    /// Kind of artifact set (`model`, `dataset`, `evaluation`, ...); selects
+    /// the schema in the `SchemaRegistry` the rest of the document must pass.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Solana public key of the author; anchors are derived from it.
+    pub creator: String,
+    /// Node that produced and first published the manifest.
+    pub node_id: String,
+    /// Catalog namespace, for nodes that host several projects. Defaults to
+    /// `creator`; see `namespace()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Stable logical name shared by every version of the same artifact
+    /// set, unique within the namespace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// CID of the previous version this one replaces. Only a manifest with
+    /// the same creator and `name` can supersede another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supersedes: Option<String>,
+    /// CIDs of the manifests this one derives from (fine-tune base,
+    /// source dataset, ...).
+    #[serde(default)]
+    pub parents: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub artifacts: Vec<ArtifactRef>,
+    /// Unix timestamp chosen by the creator. Not trusted for ordering; see
+    /// the merge rules in docs/storage.md.
+    pub created_at: i64,
+    /// Private fields, encrypted to a set of keys. Present only on private
+    /// manifests; see `envelope::seal`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<Envelope>,
+    /// Base58 Ed25519 signature by `creator` over `signing_message()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// A file the manifest points at, stored in IPFS.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactRef {
+    pub name: String,
+    pub cid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManifestError {
+    /// Not JSON, or a required field is missing or has the wrong type.
+    Parse(String),
+    UnsupportedVersion(u32),
+    Invalid { field: String, reason: String },
+    /// The bytes aren't the canonical encoding of the document they hold,
+    /// so the same document could be published under several CIDs.
+    NotCanonical,
+    Unsigned,
+    BadSignature(String),
+    /// `type` has no registered schema and unknown types aren't allowed.
+    UnknownType(String),
+    /// The document fails its type's schema; one message per violation.
+    Schema { kind: String, errors: Vec<String> },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Parse(e) => write!(f, "malformed manifest: {}", e),
+            ManifestError::UnsupportedVersion(v) => write!(f, "unsupported manifest schema version {}", v),
+            ManifestError::Invalid { field, reason } => write!(f, "invalid manifest field `{}`: {}", field, reason),
+            ManifestError::NotCanonical => write!(f, "manifest is not canonically encoded"),
+            ManifestError::Unsigned => write!(f, "manifest is not signed by its creator"),
+            ManifestError::BadSignature(e) => write!(f, "bad creator signature: {}", e),
+            ManifestError::UnknownType(kind) => write!(f, "no schema registered for manifest type `{}`", kind),
+            ManifestError::Schema { kind, errors } => {
+                write!(f, "manifest does not match the `{}` schema: {}", kind, errors.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+impl ManifestDocument {
+    /// Parses and validates manifest bytes.
+    pub fn parse(data: &[u8]) -> Result<Self, ManifestError> {
+        let document: Self = serde_json::from_slice(data).map_err(|e| ManifestError::Parse(e.to_string()))?;
+        document.validate()?;
+        Ok(document)
+    }
+
+    /// Canonical encoding: compact JSON with object keys sorted at every
+    /// level. Going through `Value` sorts the keys because serde_json's map
+    /// is a `BTreeMap`; the `preserve_order` feature must stay off.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let value = serde_json::to_value(self).expect("manifest documents always serialize");
+        serde_json::to_vec(&value).expect("JSON values always serialize")
+    }
+
+    /// What the creator signs: the canonical encoding without `signature`.
+    pub fn signing_message(&self) -> Vec<u8> {
+        let unsigned = Self { signature: None, ..self.clone() };
+        let mut message = b"neuroswarm-manifest:".to_vec();
+        message.extend(unsigned.to_bytes());
+        message
+    }
+
+    /// Signs the document; `keypair` must belong to `creator`.
+    pub fn sign(&mut self, keypair: &Keypair) -> Result<(), ManifestError> {
+        if keypair.pubkey().to_string() != self.creator {
+            return Err(ManifestError::BadSignature("signing key is not the creator".to_string()));
+        }
+        self.signature = Some(keypair.sign_message(&self.signing_message()).to_string());
+        Ok(())
+    }
+
+    pub fn verify_signature(&self) -> Result<(), ManifestError> {
+        let signature = self.signature.as_deref().ok_or(ManifestError::Unsigned)?;
+        let signature = Signature::from_str(signature).map_err(|e| ManifestError::BadSignature(e.to_string()))?;
+        let creator = Pubkey::from_str(&self.creator).map_err(|e| ManifestError::BadSignature(e.to_string()))?;
+        if !signature.verify(creator.as_ref(), &self.signing_message()) {
+            return Err(ManifestError::BadSignature("does not match creator".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The explicit `namespace`, or the creator's key.
+    pub fn namespace(&self) -> &str {
+        self.namespace.as_deref().unwrap_or(&self.creator)
+    }
+
+    /// Whether a newer build wrote this document, so `extra` may hold
+    /// fields with meaning this build can't interpret.
+    pub fn is_newer_schema(&self) -> bool {
+        self.schema_version > MANIFEST_SCHEMA_VERSION
+    }
+
+    pub fn validate(&self) -> Result<(), ManifestError> {
+        if self.schema_version == 0 {
+            return Err(ManifestError::UnsupportedVersion(0));
+        }
+        if Pubkey::from_str(&self.creator).is_err() {
+            return Err(invalid("creator", "not a base58 public key"));
+        }
+        if self.node_id.trim().is_empty() {
+            return Err(invalid("node_id", "must not be empty"));
+        }
+        if let Some(namespace) = &self.namespace {
+            if !is_namespace(namespace) {
+                return Err(invalid("namespace", "must be 1-64 characters of [A-Za-z0-9._-]"));
+            }
+        }
+        if let Some(envelope) = &self.encrypted {
+            envelope.validate().map_err(|reason| invalid("encrypted", &reason))?;
+        }
+        if let Some(name) = &self.name {
+            if name.trim().is_empty() || name.len() > MAX_NAME_LEN || name.chars().any(char::is_control) {
+                return Err(invalid("name", &format!("must be 1-{} bytes without control characters", MAX_NAME_LEN)));
+            }
+        }
+        if let Some(previous) = &self.supersedes {
+            if !is_cid(previous) {
+                return Err(invalid("supersedes", "not a CID"));
+            }
+            if self.name.is_none() {
+                return Err(invalid("supersedes", "requires a `name` to version"));
+            }
+        }
+        if self.created_at <= 0 {
+            return Err(invalid("created_at", "must be a positive unix timestamp"));
+        }
+        let mut seen = HashSet::new();
+        for (i, parent) in self.parents.iter().enumerate() {
+            if !is_cid(parent) {
+                return Err(invalid(&format!("parents[{}]", i), "not a CID"));
+            }
+            if !seen.insert(parent) {
+                return Err(invalid(&format!("parents[{}]", i), "duplicate parent"));
+            }
+        }
+        if self.tags.len() > MAX_TAGS {
+            return Err(invalid("tags", &format!("at most {} tags", MAX_TAGS)));
+        }
+        for (i, tag) in self.tags.iter().enumerate() {
+            if tag.is_empty() || tag.len() > MAX_TAG_LEN {
+                return Err(invalid(&format!("tags[{}]", i), &format!("must be 1-{} bytes", MAX_TAG_LEN)));
+            }
+        }
+        for (i, artifact) in self.artifacts.iter().enumerate() {
+            if artifact.name.is_empty() {
+                return Err(invalid(&format!("artifacts[{}].name", i), "must not be empty"));
+            }
+            if !is_cid(&artifact.cid) {
+                return Err(invalid(&format!("artifacts[{}].cid", i), "not a CID"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(field: &str, reason: &str) -> ManifestError {
+    ManifestError::Invalid {
+        field: field.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+pub fn is_namespace(namespace: &str) -> bool {
+    (1..=64).contains(&namespace.len())
+        && namespace.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// CIDv0 syntax check; the catalog doesn't handle other versions yet.
+fn is_cid(cid: &str) -> bool {
+    cid.starts_with("Qm") && bs58::decode(cid).into_vec().map_or(false, |bytes| bytes.len() == 34)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREATOR: &str = "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS";
+    const PARENT: &str = "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n";
+
+    #[test]
+    fn test_parse_and_validate() {
+        let data = serde_json::json!({
+            "schema_version": 2,
+            "creator": CREATOR,
+            "node_id": "node1",
+            "parents": [PARENT],
+            "tags": ["llm"],
+            "artifacts": [{"name": "weights", "cid": PARENT, "size": 10, "checksum": "abc"}],
+            "created_at": 1700000000,
+            "license": "apache-2.0"
+        });
+        let document = ManifestDocument::parse(data.to_string().as_bytes()).unwrap();
+        assert!(document.is_newer_schema());
+        assert_eq!(document.parents, vec![PARENT.to_string()]);
+        // Unknown fields survive a round trip
+        assert_eq!(document.extra["license"], "apache-2.0");
+        assert_eq!(document.artifacts[0].extra["checksum"], "abc");
+        assert_eq!(ManifestDocument::parse(&document.to_bytes()).unwrap(), document);
+
+        let mut bad = data.clone();
+        bad["parents"] = serde_json::json!(["not-a-cid"]);
+        assert!(matches!(
+            ManifestDocument::parse(bad.to_string().as_bytes()),
+            Err(ManifestError::Invalid { field, .. }) if field == "parents[0]"
+        ));
+        assert!(matches!(ManifestDocument::parse(b"\x01\x02"), Err(ManifestError::Parse(_))));
+
+        let mut unnamed = data.clone();
+        unnamed["supersedes"] = PARENT.into();
+        assert!(matches!(
+            ManifestDocument::parse(unnamed.to_string().as_bytes()),
+            Err(ManifestError::Invalid { field, .. }) if field == "supersedes"
+        ));
+    }
+
+    #[test]
+    fn test_canonical_signed_manifest() {
+        let creator = Keypair::new();
+        let data = serde_json::json!({
+            "schema_version": 1,
+            "creator": creator.pubkey().to_string(),
+            "node_id": "node1",
+            "tags": ["llm"],
+            "created_at": 1700000000
+        });
+        let mut document = ManifestDocument::parse(data.to_string().as_bytes()).unwrap();
+        document.sign(&creator).unwrap();
+        let data = document.to_bytes();
+        let manifest = Manifest { cid: crate::ipfs::compute_cid(&data), data, timestamp: 1 };
+        assert_eq!(manifest.verify_authorship().unwrap(), document);
+
+        // Same document, different bytes
+        let pretty = serde_json::to_vec_pretty(&document).unwrap();
+        let reencoded = Manifest { data: pretty, ..manifest.clone() };
+        assert_eq!(reencoded.verify_authorship(), Err(ManifestError::NotCanonical));
+
+        let mut forged = document.clone();
+        forged.tags.push("forged".to_string());
+        let forged = Manifest { data: forged.to_bytes(), ..manifest.clone() };
+        assert!(matches!(forged.verify_authorship(), Err(ManifestError::BadSignature(_))));
+
+        let unsigned = ManifestDocument { signature: None, ..document.clone() };
+        assert_eq!(unsigned.verify_signature(), Err(ManifestError::Unsigned));
+        assert!(document.clone().sign(&Keypair::new()).is_err());
+    }
+}
@@ -0,0 +1,409 @@
+use rusqlite::{ffi, Connection, Error, Result};
+use tracing::info;
+
+/// One ordered step in the catalog schema. `version` is the `user_version`
+/// the database reports once the step has been applied.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+}
+
+/// Every schema step, oldest first. Append only: never edit or reorder a
+/// step once it has shipped. Steps use `IF NOT EXISTS` so catalogs created
+/// before versioning (user_version 0, tables already present) upgrade cleanly.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "baseline manifests and provenance tables",
+        apply: baseline,
+    },
+    Migration {
+        version: 2,
+        description: "tombstones for evicted and retracted manifests",
+        apply: tombstones,
+    },
+    Migration {
+        version: 3,
+        description: "signed validator attestations",
+        apply: attestations,
+    },
+    Migration {
+        version: 4,
+        description: "anchored timestamps and manifest conflict log",
+        apply: conflicts,
+    },
+    Migration {
+        version: 5,
+        description: "persisted per-peer sync state",
+        apply: sync_state,
+    },
+    Migration {
+        version: 6,
+        description: "index manifests by (timestamp, cid) for range and keyset queries",
+        apply: timestamp_index,
+    },
+    Migration {
+        version: 7,
+        description: "blob keys and payload sizes for externally stored manifest data",
+        apply: blob_keys,
+    },
+    Migration {
+        version: 8,
+        description: "anchor block time in the provenance cache",
+        apply: provenance_anchored_at,
+    },
+    Migration {
+        version: 9,
+        description: "rejection reasons for manifests that failed validation",
+        apply: manifest_rejections,
+    },
+    Migration {
+        version: 10,
+        description: "manifest namespaces",
+        apply: namespaces,
+    },
+    Migration {
+        version: 11,
+        description: "manifest creator, type and tags for filtered sync",
+        apply: manifest_metadata,
+    },
+    Migration {
+        version: 12,
+        description: "signed retraction records",
+        apply: retractions,
+    },
+    Migration {
+        version: 13,
+        description: "namespace, type and tags of retracted manifests for filtered sync",
+        apply: retraction_scope,
+    },
+    Migration {
+        version: 14,
+        description: "peer each retraction record came from",
+        apply: retraction_source,
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Steps not yet applied to `conn`. Fails if the database was written by a
+/// newer binary.
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>> {
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISMATCH),
+            Some(format!(
+                "catalog schema version {} is newer than supported version {}",
+                current,
+                latest_version()
+            )),
+        ));
+    }
+    Ok(MIGRATIONS.iter().filter(|m| m.version > current).collect())
+}
+
+/// Applies pending steps in order, each in its own transaction, and returns
+/// them. With `dry_run` nothing is written.
+pub fn migrate(conn: &Connection, dry_run: bool) -> Result<Vec<&'static Migration>> {
+    let steps = pending(conn)?;
+    if dry_run {
+        return Ok(steps);
+    }
+    for step in &steps {
+        let tx = conn.unchecked_transaction()?;
+        (step.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", step.version)?;
+        tx.commit()?;
+        info!("Migrated catalog to schema v{}: {}", step.version, step.description);
+    }
+    Ok(steps)
+}
+
+fn baseline(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS manifests (
+            cid TEXT PRIMARY KEY,
+            data BLOB,
+            timestamp INTEGER
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS provenance (
+            cid TEXT PRIMARY KEY,
+            finalized BOOLEAN,
+            attestation_count INTEGER,
+            tx_signature TEXT,
+            slot INTEGER,
+            cached_at INTEGER,
+            FOREIGN KEY(cid) REFERENCES manifests(cid)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn tombstones(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tombstones (
+            cid TEXT PRIMARY KEY,
+            deleted_at INTEGER,
+            reason TEXT
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn attestations(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS attestations (
+            id INTEGER PRIMARY KEY,
+            manifest_cid TEXT,
+            validator TEXT,
+            confidence REAL,
+            timestamp INTEGER,
+            signature TEXT,
+            UNIQUE(manifest_cid, validator),
+            FOREIGN KEY(manifest_cid) REFERENCES manifests(cid)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn conflicts(conn: &Connection) -> Result<()> {
+    ensure_column(conn, "manifests", "timestamp_anchored", "BOOLEAN NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS manifest_conflicts (
+            id INTEGER PRIMARY KEY,
+            cid TEXT,
+            source TEXT,
+            kind TEXT,
+            existing_timestamp INTEGER,
+            incoming_timestamp INTEGER,
+            detected_at INTEGER
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn sync_state(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_state (
+            peer TEXT PRIMARY KEY,
+            last_sync INTEGER,
+            started_at INTEGER,
+            pages_fetched INTEGER,
+            manifests_received INTEGER,
+            pending_verification INTEGER,
+            advertised_total INTEGER,
+            complete BOOLEAN,
+            updated_at INTEGER
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn timestamp_index(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_manifests_timestamp ON manifests(timestamp, cid)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Existing rows keep their inline `data` until `Storage::externalize_blobs`
+/// moves it out; only the size is backfilled here.
+fn blob_keys(conn: &Connection) -> Result<()> {
+    ensure_column(conn, "manifests", "blob_key", "TEXT")?;
+    ensure_column(conn, "manifests", "size", "INTEGER")?;
+    conn.execute("UPDATE manifests SET size = length(data) WHERE size IS NULL", [])?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_manifests_blob_key ON manifests(blob_key)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn provenance_anchored_at(conn: &Connection) -> Result<()> {
+    ensure_column(conn, "provenance", "anchored_at", "INTEGER")
+}
+
+fn manifest_rejections(conn: &Connection) -> Result<()> {
+    // No foreign key: rejected manifests are by definition not stored
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS manifest_rejections (
+            cid TEXT PRIMARY KEY,
+            source TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            rejected_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn namespaces(conn: &Connection) -> Result<()> {
+    // Deriving a namespace means parsing the payload, which may be in the
+    // blob store, so existing rows stay NULL until `Storage` backfills them
+    ensure_column(conn, "manifests", "namespace", "TEXT")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_manifests_namespace ON manifests (namespace, timestamp, cid)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn manifest_metadata(conn: &Connection) -> Result<()> {
+    ensure_column(conn, "manifests", "creator", "TEXT")?;
+    ensure_column(conn, "manifests", "kind", "TEXT")?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS manifest_tags (
+            cid TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (tag, cid),
+            FOREIGN KEY(cid) REFERENCES manifests(cid) ON DELETE CASCADE
+         );
+         CREATE INDEX IF NOT EXISTS idx_manifests_creator ON manifests (creator, timestamp, cid);
+         CREATE INDEX IF NOT EXISTS idx_manifest_tags_cid ON manifest_tags (cid);",
+    )?;
+    // A NULL namespace marks a row for `Storage`'s backfill, which now
+    // fills in all of the document columns
+    conn.execute("UPDATE manifests SET namespace = NULL", [])?;
+    Ok(())
+}
+
+fn retractions(conn: &Connection) -> Result<()> {
+    // No foreign key: a record outlives its manifest and may arrive first
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS retractions (
+            cid TEXT NOT NULL,
+            creator TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            retracted_at INTEGER NOT NULL,
+            signature TEXT NOT NULL,
+            received_at INTEGER NOT NULL,
+            PRIMARY KEY (cid, creator)
+         )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn retraction_scope(conn: &Connection) -> Result<()> {
+    // Existing records stay NULL and only go to peers that replicate everything
+    ensure_column(conn, "retractions", "namespace", "TEXT")?;
+    ensure_column(conn, "retractions", "kind", "TEXT")?;
+    ensure_column(conn, "retractions", "tags", "TEXT")
+}
+
+fn retraction_source(conn: &Connection) -> Result<()> {
+    // Existing and locally made records stay NULL and count against no peer
+    ensure_column(conn, "retractions", "source", "TEXT")?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_retractions_source ON retractions(source)", [])?;
+    Ok(())
+}
+
+/// Adds a column unless an unversioned catalog already has it.
+fn ensure_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|name| name.ok())
+        .any(|name| name == column);
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    /// A catalog as written by the original, unversioned `Storage::new`.
+    fn baseline_fixture() -> Result<Connection> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE manifests (cid TEXT PRIMARY KEY, data BLOB, timestamp INTEGER);
+             CREATE TABLE provenance (
+                cid TEXT PRIMARY KEY, finalized BOOLEAN, attestation_count INTEGER,
+                tx_signature TEXT, slot INTEGER, cached_at INTEGER,
+                FOREIGN KEY(cid) REFERENCES manifests(cid)
+             );",
+        )?;
+        conn.execute(
+            "INSERT INTO manifests (cid, data, timestamp) VALUES (?1, ?2, ?3)",
+            params!["QmBaseline", vec![1u8, 2, 3], 42],
+        )?;
+        conn.execute(
+            "INSERT INTO provenance (cid, finalized, attestation_count, tx_signature, slot, cached_at)
+             VALUES (?1, 1, 3, 'sig', 7, 100)",
+            params!["QmBaseline"],
+        )?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn test_upgrade_baseline_fixture() -> Result<()> {
+        let conn = baseline_fixture()?;
+        assert_eq!(current_version(&conn)?, 0);
+
+        let applied = migrate(&conn, false)?;
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(current_version(&conn)?, latest_version());
+
+        // Existing rows survive and pick up new column defaults
+        let (timestamp, anchored, size): (i64, bool, i64) = conn.query_row(
+            "SELECT timestamp, timestamp_anchored, size FROM manifests WHERE cid = 'QmBaseline'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        assert_eq!((timestamp, anchored, size), (42, false, 3));
+        let slot: i64 = conn.query_row("SELECT slot FROM provenance WHERE cid = 'QmBaseline'", [], |row| row.get(0))?;
+        assert_eq!(slot, 7);
+        for table in ["tombstones", "attestations", "manifest_conflicts", "sync_state"] {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table],
+                |row| row.get(0),
+            )?;
+            assert_eq!(count, 1, "missing table {}", table);
+        }
+
+        // Re-running is a no-op
+        assert!(migrate(&conn, false)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_leaves_schema_untouched() -> Result<()> {
+        let conn = baseline_fixture()?;
+        let planned = migrate(&conn, true)?;
+        assert_eq!(planned.len(), MIGRATIONS.len());
+        assert_eq!(current_version(&conn)?, 0);
+        let tables: i64 = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'", [], |row| row.get(0))?;
+        assert_eq!(tables, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_newer_schema() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        conn.pragma_update(None, "user_version", latest_version() + 1)?;
+        assert!(migrate(&conn, false).is_err());
+        Ok(())
+    }
+}
@@ -1,19 +1,77 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use quinn::{ClientConfig, Endpoint, ServerConfig, Connection};
-use rustls::Certificate;
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
+use quinn::{ClientConfig, Endpoint, ServerConfig, Connection, RecvStream, SendStream};
+use rustls::{Certificate, PrivateKey};
+use sha2::{Digest, Sha256};
 use anyhow::Result;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 use serde::{Serialize, Deserialize};
 
+use crate::manifest::Manifest;
+use crate::retraction::Retraction;
+use crate::storage::{Attestation, CatalogFilter, ManifestCursor};
+
+/// Upper bound on a single sync reply, to cap memory per stream.
+const MAX_SYNC_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum SyncMessage {
-    RequestCatalog { since: Option<i64> },
-    CatalogChunk { manifests: Vec<Manifest>, has_more: bool },
+    RequestCatalog {
+        since: Option<i64>,
+        /// Resume after this position, taken from the previous chunk's `next`.
+        #[serde(default)]
+        after: Option<ManifestCursor>,
+        /// Only manifests matching this; empty for everything the peer serves.
+        #[serde(default)]
+        filter: CatalogFilter,
+    },
+    CatalogChunk {
+        manifests: Vec<Manifest>,
+        /// Signed retractions, on the first page only.
+        #[serde(default)]
+        retractions: Vec<Retraction>,
+        has_more: bool,
+        /// Cursor to request the following page with.
+        #[serde(default)]
+        next: Option<ManifestCursor>,
+        /// Number of manifests matching the request on the serving side.
+        #[serde(default)]
+        total: Option<u64>,
+    },
+    /// Unsolicited push of a newly published manifest.
+    AnnounceManifest { manifest: Manifest },
+    /// Unsolicited push of a creator's retraction.
+    AnnounceRetraction { retraction: Retraction },
     RequestManifest { cid: String },
     ManifestData { cid: String, data: Vec<u8> },
+    ManifestNotFound { cid: String },
+    RequestAttestations { cid: String },
+    Attestations { cid: String, attestations: Vec<Attestation> },
+}
+
+/// A sync message from a peer, waiting for the local `SyncEngine`.
+pub struct SyncRequest {
+    pub addr: SocketAddr,
+    pub message: SyncMessage,
+    /// Written back on the peer's stream; `None` finishes it without a reply.
+    pub reply: oneshot::Sender<Option<SyncMessage>>,
+}
+
+/// Where incoming sync messages go, once something has asked for them.
+type Requests = Arc<RwLock<Option<mpsc::UnboundedSender<SyncRequest>>>>;
+
+/// The node id each peer address is bound to.
+type Pins = Arc<RwLock<HashMap<SocketAddr, String>>>;
+
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    pub node_id: String,
+    pub version: String,
 }
 
 pub struct Network {
@@ -21,73 +79,125 @@ pub struct Network {
     peers: Arc<RwLock<HashMap<SocketAddr, Peer>>>,
     connections: Arc<RwLock<HashMap<SocketAddr, Connection>>>,
     banlist: Arc<RwLock<HashMap<SocketAddr, std::time::Instant>>>,
+    requests: Requests,
+    /// Announces each peer whose handshake completes.
+    connected: broadcast::Sender<SocketAddr>,
+    pins: Pins,
+    /// Fingerprint of this node's certificate, sent in the handshake.
+    node_id: String,
     config: NetworkConfig,
 }
 
 #[derive(Clone)]
 pub struct NetworkConfig {
     pub dns_seeds: Vec<String>,
+    /// `host:port`, or `<node id>@host:port` to pin the peer's certificate.
     pub static_peers: Vec<String>,
     pub listen_addr: String,
     pub max_peers: usize,
+    /// Where `node.crt` and `node.key` live; `None` for a throwaway
+    /// identity that changes every run.
+    pub identity_dir: Option<PathBuf>,
+    /// Accept peers whose certificate doesn't match their node id.
+    pub insecure_skip_verify: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Manifest {
-    pub cid: String,
-    pub data: Vec<u8>,
-    pub timestamp: i64,
+/// What a handshake needs to register a peer.
+#[derive(Clone)]
+struct PeerContext {
+    peers: Arc<RwLock<HashMap<SocketAddr, Peer>>>,
+    connections: Arc<RwLock<HashMap<SocketAddr, Connection>>>,
+    banlist: Arc<RwLock<HashMap<SocketAddr, std::time::Instant>>>,
+    requests: Requests,
+    connected: broadcast::Sender<SocketAddr>,
+    pins: Pins,
+    node_id: String,
+    insecure_skip_verify: bool,
 }
 
 impl Network {
     pub async fn new(config: NetworkConfig) -> Result<Arc<Self>> {
-        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
-        let cert_der = cert.serialize_der()?;
-        let priv_key = cert.serialize_private_key_der();
-        let priv_key = rustls::PrivateKey(priv_key);
-        let cert_chain = vec![Certificate(cert_der)];
+        let (cert, key) = load_identity(config.identity_dir.as_deref())?;
+        let node_id = fingerprint(&cert);
 
-        let server_config = ServerConfig::with_single_cert(cert_chain.clone(), priv_key)?;
-        let mut endpoint = Endpoint::server(server_config, config.listen_addr.parse()?)?;
+        // Every node has its own self-signed certificate, so there is no CA
+        // to check against. Both sides present one, and the handshake then
+        // binds it to the node id the peer claims (see `verify_peer`).
+        let server_crypto = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(Arc::new(SelfSignedPeer))
+            .with_single_cert(vec![cert.clone()], key.clone())?;
+        let mut endpoint = Endpoint::server(ServerConfig::with_crypto(Arc::new(server_crypto)), config.listen_addr.parse()?)?;
 
-        let client_config = ClientConfig::with_custom_certificate(cert_chain)?;
-        endpoint.set_default_client_config(client_config);
+        let client_crypto = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(SelfSignedPeer))
+            .with_client_auth_cert(vec![cert], key)?;
+        endpoint.set_default_client_config(ClientConfig::new(Arc::new(client_crypto)));
+        let (connected, _) = broadcast::channel(64);
+        if config.insecure_skip_verify {
+            warn!("network.insecure_skip_verify is set; peers' node ids are not checked");
+        }
 
         Ok(Arc::new(Self {
             endpoint,
             peers: Arc::new(RwLock::new(HashMap::new())),
             connections: Arc::new(RwLock::new(HashMap::new())),
             banlist: Arc::new(RwLock::new(HashMap::new())),
+            requests: Arc::new(RwLock::new(None)),
+            connected,
+            pins: Arc::new(RwLock::new(HashMap::new())),
+            node_id,
             config,
         }))
     }
 
-    pub async fn start(&self) -> Result<()> {
-        // Resolve DNS seeds
-        for seed in &self.config.dns_seeds {
-            if let Ok(addrs) = tokio::net::lookup_host(seed).await {
-                for addr in addrs {
-                    self.connect_peer(addr).await?;
-                }
-            }
-        }
+    /// This node's id: the SHA-256 fingerprint of its certificate.
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
 
-        // Connect to static peers
-        for peer in &self.config.static_peers {
-            if let Ok(addr) = peer.parse() {
-                self.connect_peer(addr).await?;
-            }
+    fn context(&self) -> PeerContext {
+        PeerContext {
+            peers: self.peers.clone(),
+            connections: self.connections.clone(),
+            banlist: self.banlist.clone(),
+            requests: self.requests.clone(),
+            connected: self.connected.clone(),
+            pins: self.pins.clone(),
+            node_id: self.node_id.clone(),
+            insecure_skip_verify: self.config.insecure_skip_verify,
         }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// Routes sync messages from peers, and replies to messages this node
+    /// sent, to the returned receiver. Until this is called they are
+    /// dropped unanswered.
+    pub async fn incoming(&self) -> mpsc::UnboundedReceiver<SyncRequest> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.requests.write().await = Some(sender);
+        receiver
+    }
 
-        // Listen for incoming connections
+    /// Yields the address of every peer whose handshake completes from now
+    /// on, inbound or outbound.
+    pub fn subscribe_connections(&self) -> broadcast::Receiver<SocketAddr> {
+        self.connected.subscribe()
+    }
+
+    /// Accepts inbound connections until the endpoint closes. Dialing out
+    /// is `connect_bootstrap_peers`.
+    pub async fn start(&self) -> Result<()> {
         while let Some(conn) = self.endpoint.accept().await {
-            let peers = self.peers.clone();
-            let connections = self.connections.clone();
-            let banlist = self.banlist.clone();
+            let context = self.context();
             tokio::spawn(async move {
                 if let Ok(conn) = conn.await {
                     // Handle handshake
-                    if let Err(e) = handle_handshake(conn, peers, connections, banlist).await {
+                    if let Err(e) = handle_handshake(conn, false, context).await {
                         warn!("Handshake failed: {:?}", e);
                     }
                 }
@@ -97,6 +207,53 @@ impl Network {
         Ok(())
     }
 
+    /// Dials DNS seeds and static peers without accepting inbound
+    /// connections. A peer that can't be reached is logged and skipped.
+    pub async fn connect_bootstrap_peers(&self) -> Result<()> {
+        let mut addrs = Vec::new();
+        // Resolve DNS seeds
+        for seed in &self.config.dns_seeds {
+            if let Ok(resolved) = tokio::net::lookup_host(seed).await {
+                addrs.extend(resolved);
+            }
+        }
+        // Static peers, pinning the ones configured with a node id
+        for peer in &self.config.static_peers {
+            let (node_id, addr) = match peer.split_once('@') {
+                Some((node_id, addr)) => (Some(node_id), addr),
+                None => (None, peer.as_str()),
+            };
+            let Ok(addr) = addr.parse::<SocketAddr>() else { continue };
+            if let Some(node_id) = node_id {
+                self.pins.write().await.insert(addr, node_id.to_lowercase());
+            }
+            addrs.push(addr);
+        }
+
+        for addr in addrs {
+            if let Err(e) = self.connect_peer(addr).await {
+                warn!("Failed to connect to {}: {:?}", addr, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until at least one handshake completes or `timeout` elapses.
+    pub async fn wait_for_peers(&self, timeout: std::time::Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let count = self.peers.read().await.len();
+            if count > 0 || tokio::time::Instant::now() >= deadline {
+                return count;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+    }
+
+    pub async fn peer_addrs(&self) -> Vec<SocketAddr> {
+        self.peers.read().await.keys().copied().collect()
+    }
+
     async fn connect_peer(&self, addr: SocketAddr) -> Result<()> {
         if self.peers.read().await.len() >= self.config.max_peers {
             return Ok(());
@@ -106,11 +263,9 @@ impl Network {
         }
 
         let conn = self.endpoint.connect(addr, "localhost")?.await?;
-        let peers = self.peers.clone();
-        let connections = self.connections.clone();
-        let banlist = self.banlist.clone();
+        let context = self.context();
         tokio::spawn(async move {
-            if let Err(e) = handle_handshake(conn, peers, connections, banlist).await {
+            if let Err(e) = handle_handshake(conn, true, context).await {
                 warn!("Outgoing handshake failed: {:?}", e);
             }
         });
@@ -118,31 +273,73 @@ impl Network {
         Ok(())
     }
 
+    /// Sends a sync message without waiting for the peer. A reply, such as
+    /// the `CatalogChunk` answering a `RequestCatalog`, is handled like any
+    /// message from that peer.
     pub async fn send_sync_message(&self, addr: SocketAddr, message: &SyncMessage) -> Result<()> {
-        if let Some(conn) = self.connections.read().await.get(&addr) {
-            let (mut send, mut recv) = conn.open_bi().await?;
-            let data = serde_json::to_vec(message)?;
-            send.write_all(&data).await?;
-            send.finish().await?;
-            // Optionally read response
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("No connection to peer"))
+        let conn = self
+            .connections
+            .read()
+            .await
+            .get(&addr)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No connection to peer"))?;
+        let (mut send, mut recv) = conn.open_bi().await?;
+        let data = serde_json::to_vec(message)?;
+        send.write_all(&data).await?;
+        send.finish().await?;
+        let requests = self.requests.clone();
+        tokio::spawn(async move {
+            let reply = match recv.read_to_end(MAX_SYNC_MESSAGE_SIZE).await {
+                Ok(data) if data.is_empty() => return,
+                Ok(data) => data,
+                Err(e) => {
+                    warn!("Failed to read reply from {}: {:?}", addr, e);
+                    return;
+                }
+            };
+            match serde_json::from_slice(&reply) {
+                Ok(reply) => {
+                    if let Err(e) = dispatch(addr, reply, &requests).await {
+                        warn!("Failed to handle reply from {}: {:?}", addr, e);
+                    }
+                }
+                Err(e) => warn!("Malformed reply from {}: {}", addr, e),
+            }
+        });
+        Ok(())
+    }
+
+    /// Sends a sync message and waits for the peer's reply on the same stream.
+    pub async fn request_sync_message(&self, addr: SocketAddr, message: &SyncMessage) -> Result<SyncMessage> {
+        let conn = self
+            .connections
+            .read()
+            .await
+            .get(&addr)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No connection to peer"))?;
+        let (mut send, mut recv) = conn.open_bi().await?;
+        let data = serde_json::to_vec(message)?;
+        send.write_all(&data).await?;
+        send.finish().await?;
+        let response = recv.read_to_end(MAX_SYNC_MESSAGE_SIZE).await?;
+        if response.is_empty() {
+            return Err(anyhow::anyhow!("{} sent no reply", addr));
         }
+        Ok(serde_json::from_slice(&response)?)
     }
 }
 
-async fn handle_handshake(
-    conn: quinn::Connection,
-    peers: Arc<RwLock<HashMap<SocketAddr, Peer>>>,
-    connections: Arc<RwLock<HashMap<SocketAddr, Connection>>>,
-    banlist: Arc<RwLock<HashMap<SocketAddr, std::time::Instant>>>,
-) -> Result<()> {
-    let (mut send, mut recv) = conn.accept_bi().await?;
+/// Exchanges handshakes on a new connection. The dialing side opens the
+/// stream; a QUIC stream only reaches the peer once data is written on it.
+async fn handle_handshake(conn: quinn::Connection, outgoing: bool, context: PeerContext) -> Result<()> {
+    let PeerContext { peers, connections, banlist, requests, connected, pins, node_id, insecure_skip_verify } = context;
+    let (mut send, mut recv) = if outgoing { conn.open_bi().await? } else { conn.accept_bi().await? };
 
     // Send handshake
     let handshake = Handshake {
-        node_id: "node123".to_string(),
+        node_id,
         version: "0.1.0".to_string(),
     };
     let data = serde_json::to_vec(&handshake)?;
@@ -150,15 +347,20 @@ async fn handle_handshake(
     send.finish().await?;
 
     // Receive handshake
-    let mut buf = vec![0; 1024];
-    let n = recv.read(&mut buf).await?.unwrap();
-    let peer_handshake: Handshake = serde_json::from_slice(&buf[..n])?;
+    let data = recv.read_to_end(1024).await?;
+    let peer_handshake: Handshake = serde_json::from_slice(&data)?;
 
     if peer_handshake.version != "0.1.0" {
         // Ban for incompatible version
         banlist.write().await.insert(conn.remote_address(), std::time::Instant::now() + std::time::Duration::from_secs(3600));
         return Err(anyhow::anyhow!("Version mismatch"));
     }
+    if !insecure_skip_verify {
+        if let Err(e) = verify_peer(&conn, &peer_handshake.node_id, &pins).await {
+            banlist.write().await.insert(conn.remote_address(), std::time::Instant::now() + std::time::Duration::from_secs(3600));
+            return Err(e);
+        }
+    }
 
     let peer = Peer {
         addr: conn.remote_address(),
@@ -168,10 +370,12 @@ async fn handle_handshake(
     peers.write().await.insert(conn.remote_address(), peer);
     connections.write().await.insert(conn.remote_address(), conn.clone());
     info!("Connected to peer: {:?}", conn.remote_address());
+    // Nobody listening is fine; the peer is in the table either way
+    let _ = connected.send(conn.remote_address());
 
     // Start message handling loop
     tokio::spawn(async move {
-        if let Err(e) = handle_messages(conn, peers, connections).await {
+        if let Err(e) = handle_messages(conn, peers, connections, requests).await {
             warn!("Message handling failed: {:?}", e);
         }
     });
@@ -179,6 +383,167 @@ async fn handle_handshake(
     Ok(())
 }
 
+/// Answers every stream the peer opens on `conn` until the connection
+/// closes, then forgets the peer.
+async fn handle_messages(
+    conn: Connection,
+    peers: Arc<RwLock<HashMap<SocketAddr, Peer>>>,
+    connections: Arc<RwLock<HashMap<SocketAddr, Connection>>>,
+    requests: Requests,
+) -> Result<()> {
+    let addr = conn.remote_address();
+    let result = loop {
+        match conn.accept_bi().await {
+            Ok((send, recv)) => {
+                let requests = requests.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = answer(addr, send, recv, &requests).await {
+                        warn!("Failed to answer sync message from {}: {:?}", addr, e);
+                    }
+                });
+            }
+            Err(quinn::ConnectionError::ApplicationClosed(_)) | Err(quinn::ConnectionError::LocallyClosed) => {
+                break Ok(());
+            }
+            Err(e) => break Err(e.into()),
+        }
+    };
+    peers.write().await.remove(&addr);
+    connections.write().await.remove(&addr);
+    info!("Disconnected from peer: {}", addr);
+    result
+}
+
+/// Reads one message from a peer's stream, waits for the sync engine's
+/// reply and writes it back before finishing the stream.
+async fn answer(addr: SocketAddr, mut send: SendStream, mut recv: RecvStream, requests: &Requests) -> Result<()> {
+    let data = recv.read_to_end(MAX_SYNC_MESSAGE_SIZE).await?;
+    let message: SyncMessage = serde_json::from_slice(&data)?;
+    if let Some(reply) = dispatch(addr, message, requests).await? {
+        send.write_all(&serde_json::to_vec(&reply)?).await?;
+    }
+    send.finish().await?;
+    Ok(())
+}
+
+/// Hands `message` to whoever called `Network::incoming` and waits for the
+/// reply.
+async fn dispatch(addr: SocketAddr, message: SyncMessage, requests: &Requests) -> Result<Option<SyncMessage>> {
+    let Some(sender) = requests.read().await.clone() else {
+        debug!("No sync engine running; dropping message from {}", addr);
+        return Ok(None);
+    };
+    let (reply, response) = oneshot::channel();
+    sender
+        .send(SyncRequest { addr, message, reply })
+        .map_err(|_| anyhow::anyhow!("sync engine has stopped"))?;
+    // A dropped sender means the engine had nothing to say
+    Ok(response.await.unwrap_or(None))
+}
+
+/// Checks that the certificate the peer presented hashes to the node id it
+/// claims, and that its address isn't pinned to another node. The first node
+/// seen at an address is pinned for the rest of the run, so sync state,
+/// holders and the banlist, all keyed by address, stay with one node.
+async fn verify_peer(conn: &Connection, node_id: &str, pins: &Pins) -> Result<()> {
+    let addr = conn.remote_address();
+    let cert = conn
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<Certificate>>().ok())
+        .and_then(|certs| certs.first().cloned())
+        .ok_or_else(|| anyhow::anyhow!("{} presented no certificate", addr))?;
+    let actual = fingerprint(&cert);
+    if actual != node_id {
+        return Err(anyhow::anyhow!("{} claims node id {} but its certificate is {}", addr, node_id, actual));
+    }
+    match pins.write().await.entry(addr) {
+        Entry::Occupied(pin) if pin.get() != node_id => {
+            Err(anyhow::anyhow!("{} is pinned to node {}, not {}", addr, pin.get(), node_id))
+        }
+        Entry::Occupied(_) => Ok(()),
+        Entry::Vacant(pin) => {
+            pin.insert(node_id.to_string());
+            Ok(())
+        }
+    }
+}
+
+/// Hex SHA-256 of a certificate's DER encoding; a node's id.
+pub fn fingerprint(cert: &Certificate) -> String {
+    Sha256::digest(&cert.0).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Loads the node certificate and key from `dir`, creating them on first
+/// run so the node id survives restarts. `None` makes a throwaway pair.
+fn load_identity(dir: Option<&Path>) -> Result<(Certificate, PrivateKey)> {
+    let generate = || -> Result<(Vec<u8>, Vec<u8>)> {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+        Ok((cert.serialize_der()?, cert.serialize_private_key_der()))
+    };
+    let (cert, key) = match dir {
+        None => generate()?,
+        Some(dir) => {
+            let (cert_path, key_path) = (dir.join("node.crt"), dir.join("node.key"));
+            if cert_path.exists() && key_path.exists() {
+                (std::fs::read(&cert_path)?, std::fs::read(&key_path)?)
+            } else {
+                let (cert, key) = generate()?;
+                std::fs::create_dir_all(dir)?;
+                write_private(&key_path, &key)?;
+                std::fs::write(&cert_path, &cert)?;
+                info!("Created node identity in {}", dir.display());
+                (cert, key)
+            }
+        }
+    };
+    Ok((Certificate(cert), PrivateKey(key)))
+}
+
+/// Writes a file only the owner can read.
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(data)?;
+    Ok(())
+}
+
+/// Accepts any self-signed peer certificate, on either side of the
+/// connection; rustls still checks the peer holds its key. Which node the
+/// certificate belongs to is settled by `verify_peer`.
+struct SelfSignedPeer;
+
+impl rustls::server::ClientCertVerifier for SelfSignedPeer {
+    fn client_auth_root_subjects(&self) -> &[rustls::DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::server::ClientCertVerified, rustls::Error> {
+        Ok(rustls::server::ClientCertVerified::assertion())
+    }
+}
+
+impl rustls::client::ServerCertVerifier for SelfSignedPeer {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 struct Handshake {
     node_id: String,
@@ -200,4 +565,17 @@ mod tests {
         let parsed: Handshake = serde_json::from_slice(&data).unwrap();
         assert_eq!(parsed.node_id, "test");
     }
+
+    #[test]
+    fn test_identity_survives_reload() {
+        let dir = std::env::temp_dir().join(format!("nsd-identity-{}", std::process::id()));
+        let (cert, _) = load_identity(Some(&dir)).unwrap();
+        let (reloaded, _) = load_identity(Some(&dir)).unwrap();
+        let (throwaway, _) = load_identity(None).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(fingerprint(&cert), fingerprint(&reloaded));
+        assert_ne!(fingerprint(&cert), fingerprint(&throwaway));
+        assert_eq!(fingerprint(&cert).len(), 64);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use anyhow::Result;
+use tracing::debug;
+
+use crate::anchor::Anchor;
+use crate::async_storage::AsyncStorage;
+use crate::config::ProvenanceCacheConfig;
+use crate::storage::{CachedProvenance, Provenance};
+
+/// Read-through cache in front of `Anchor::get_manifest_provenance`.
+///
+/// Entries live in the catalog's `provenance` table, keyed by CID. Finalized
+/// anchors practically never change, so they are trusted far longer than
+/// unfinalized ones, which may still be rolled back or gain attestations.
+pub struct ProvenanceCache {
+    anchor: Arc<Anchor>,
+    storage: AsyncStorage,
+    config: ProvenanceCacheConfig,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+impl ProvenanceCache {
+    pub fn new(anchor: Arc<Anchor>, storage: AsyncStorage, config: ProvenanceCacheConfig) -> Self {
+        Self {
+            anchor,
+            storage,
+            config,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Provenance for `cid`, from the cache while fresh and from Solana
+    /// otherwise. Fresh chain results are cached if the manifest is stored.
+    pub async fn get(&self, cid: &str, creator: &str) -> Result<Option<Provenance>> {
+        if let Some(cached) = self.cached(cid).await? {
+            return Ok(Some(cached));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let Some(onchain) = self.anchor.get_manifest_provenance(cid, creator).await? else {
+            return Ok(None);
+        };
+        let provenance = Provenance {
+            finalized: onchain.finalized,
+            attestation_count: onchain.attestation_count,
+            tx_signature: onchain.tx_signature,
+            slot: onchain.slot,
+            anchored_at: onchain.anchored_at,
+        };
+        self.storage.cache_provenance(cid, provenance.clone()).await?;
+        Ok(Some(provenance))
+    }
+
+    /// The cached provenance for `cid` if still fresh, without going to the
+    /// chain. Only manifests that were anchored have an entry.
+    pub async fn cached(&self, cid: &str) -> Result<Option<Provenance>> {
+        if let Some(cached) = self.storage.get_cached_provenance(cid).await? {
+            if self.is_fresh(&cached, chrono::Utc::now().timestamp()) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(Some(cached.provenance));
+            }
+            debug!("Provenance for {} is stale", cid);
+        }
+        Ok(None)
+    }
+
+    /// Stores provenance obtained elsewhere, e.g. after the manifest it
+    /// belongs to has just been merged.
+    pub async fn put(&self, cid: &str, provenance: Provenance) -> Result<()> {
+        self.storage.cache_provenance(cid, provenance).await
+    }
+
+    /// Forces the next lookup for `cid` to go to the chain.
+    pub async fn invalidate(&self, cid: &str) -> Result<bool> {
+        self.storage.invalidate_provenance(cid).await
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn is_fresh(&self, cached: &CachedProvenance, now: i64) -> bool {
+        let ttl = if cached.provenance.finalized {
+            self.config.finalized_ttl_secs
+        } else {
+            self.config.unfinalized_ttl_secs
+        };
+        now - cached.cached_at < ttl as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn test_cache_hits_and_ttls() -> Result<()> {
+        let storage = AsyncStorage::spawn(MemoryStore::new());
+        storage
+            .merge_manifest(&Manifest { cid: "cid".to_string(), data: vec![1], timestamp: 1 }, "test", None)
+            .await?;
+        let anchor = Arc::new(Anchor::new("http://127.0.0.1:8899", "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS")?);
+        let cache = ProvenanceCache::new(anchor, storage, ProvenanceCacheConfig::default());
+
+        let provenance = Provenance {
+            finalized: false,
+            attestation_count: 1,
+            tx_signature: "sig".to_string(),
+            slot: 7,
+            anchored_at: Some(100),
+        };
+        cache.put("cid", provenance.clone()).await?;
+        assert_eq!(cache.get("cid", "creator").await?.unwrap().slot, 7);
+        assert_eq!(cache.stats().hits, 1);
+
+        // Unfinalized entries expire long before finalized ones
+        let entry = |finalized| CachedProvenance {
+            provenance: Provenance { finalized, ..provenance.clone() },
+            cached_at: 0,
+        };
+        assert!(!cache.is_fresh(&entry(false), 60));
+        assert!(cache.is_fresh(&entry(true), 60));
+
+        assert!(cache.invalidate("cid").await?);
+        assert!(!cache.invalidate("cid").await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+use crate::envelope;
+use crate::ipfs::{self, IpfsCache};
+use crate::manifest::{Manifest, ManifestDocument, MANIFEST_SCHEMA_VERSION};
+
+/// An artifact uploaded while publishing.
+#[derive(Debug, Clone)]
+pub struct AddedArtifact {
+    pub name: String,
+    pub cid: String,
+    pub size: u64,
+    /// False if the content was already pinned, by an earlier manifest or
+    /// by hand, so undoing this publish must leave the pin alone.
+    pub newly_pinned: bool,
+}
+
+/// Adds every artifact that names a local `path` instead of a `cid` to IPFS
+/// (which pins it) and rewrites the entry to point at the result. Paths are
+/// relative to `base_dir`, normally the manifest file's directory.
+///
+/// A `sealed` (private) draft with local paths is refused before anything
+/// is added: IPFS serves the bytes in the clear to anyone with the CID,
+/// however the manifest itself is encrypted.
+pub async fn add_artifacts(
+    draft: &mut Value,
+    base_dir: &Path,
+    ipfs: &IpfsCache,
+    sealed: bool,
+) -> Result<Vec<AddedArtifact>> {
+    let mut added = Vec::new();
+    let Some(artifacts) = draft.get_mut("artifacts").and_then(Value::as_array_mut) else {
+        return Ok(added);
+    };
+    if sealed {
+        if let Some(path) = artifacts.iter().find_map(|a| a.get("path")) {
+            bail!(
+                "artifact {} would be pinned unencrypted; encrypt it first and list it by `cid` in a private manifest",
+                path
+            );
+        }
+    }
+    let mut pinned = None;
+    for artifact in artifacts {
+        let Some(entry) = artifact.as_object_mut() else { continue };
+        let Some(path) = entry.remove("path") else { continue };
+        let path = base_dir.join(path.as_str().ok_or_else(|| anyhow!("artifact path must be a string"))?);
+        let data = std::fs::read(&path).with_context(|| format!("reading artifact {}", path.display()))?;
+        // Only asked once there is something to add
+        if pinned.is_none() {
+            pinned = Some(ipfs.pinned().await?);
+        }
+        let cid = ipfs.add_data(&data).await?;
+        let newly_pinned = !pinned.as_ref().map_or(false, |p| p.contains(&cid));
+        let name = match entry.get("name").and_then(Value::as_str) {
+            Some(name) => name.to_string(),
+            None => path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        };
+        entry.insert("name".to_string(), Value::from(name.clone()));
+        entry.insert("cid".to_string(), Value::from(cid.clone()));
+        entry.insert("size".to_string(), Value::from(data.len() as u64));
+        added.push(AddedArtifact { name, cid, size: data.len() as u64, newly_pinned });
+    }
+    Ok(added)
+}
+
+/// Turns a draft into a signed manifest ready to store. Missing
+/// `schema_version`, `creator`, `node_id` and `created_at` are filled in;
+/// a `creator` that isn't `keypair`'s key is refused rather than replaced.
+/// With `recipients`, the content is sealed to them and to the creator.
+pub fn finish(
+    mut draft: Value,
+    keypair: &Keypair,
+    node_id: &str,
+    now: i64,
+    recipients: &[Pubkey],
+) -> Result<(ManifestDocument, Manifest)> {
+    let Some(fields) = draft.as_object_mut() else {
+        bail!("manifest must be a JSON object");
+    };
+    let creator = keypair.pubkey().to_string();
+    fields.entry("schema_version").or_insert(Value::from(MANIFEST_SCHEMA_VERSION));
+    fields.entry("creator").or_insert(Value::from(creator.clone()));
+    fields.entry("node_id").or_insert(Value::from(node_id));
+    fields.entry("created_at").or_insert(Value::from(now));
+    // Re-signed below; any old signature covers different content
+    fields.remove("signature");
+    fields.remove("encrypted");
+    if fields["creator"].as_str() != Some(creator.as_str()) {
+        bail!("manifest creator {} is not this node's key {}", fields["creator"], creator);
+    }
+
+    let mut document = ManifestDocument::parse(&serde_json::to_vec(&draft)?)?;
+    if !recipients.is_empty() {
+        let mut readers = recipients.to_vec();
+        readers.push(keypair.pubkey());
+        envelope::seal(&mut document, &readers)?;
+    }
+    document.sign(keypair)?;
+    let data = document.to_bytes();
+    let manifest = Manifest { cid: ipfs::compute_cid(&data), data, timestamp: now };
+    Ok((document, manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finish_fills_and_signs() -> Result<()> {
+        let keypair = Keypair::new();
+        let draft = serde_json::json!({"type": "model", "tags": ["llm"], "signature": "stale"});
+        let (document, manifest) = finish(draft, &keypair, "node1", 1700000000, &[])?;
+        assert_eq!(document.creator, keypair.pubkey().to_string());
+        assert_eq!(document.node_id, "node1");
+        assert_eq!(manifest.verify_authorship()?, document);
+        assert!(manifest.verify_content());
+
+        let foreign = serde_json::json!({"creator": Keypair::new().pubkey().to_string()});
+        assert!(finish(foreign, &keypair, "node1", 1, &[]).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_private_drafts_refuse_local_artifacts() -> Result<()> {
+        let mut draft = serde_json::json!({"artifacts": [{"name": "weights", "path": "weights.bin"}]});
+        // Refused before the file is read or anything reaches IPFS
+        let err = add_artifacts(&mut draft, Path::new("/nonexistent"), &IpfsCache::new(), true).await.unwrap_err();
+        assert!(err.to_string().contains("unencrypted"));
+        assert_eq!(draft["artifacts"][0]["path"], "weights.bin");
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use tracing::{error, info, warn};
+
+use crate::async_storage::AsyncStorage;
+use crate::config::{RetentionConfig, StorageConfig};
+use crate::index::Index;
+use crate::ipfs::IpfsCache;
+use crate::storage::{ManifestCursor, ManifestQuery, ManifestSummary, Tombstone, TombstoneReason};
+use crate::store::CatalogStore;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictReason {
+    /// Older than `max_age_days`.
+    Expired,
+    /// Oldest first until the catalog fits in `max_catalog_mb`.
+    OverSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protection {
+    Finalized,
+    Pinned,
+    LineageAncestor,
+}
+
+#[derive(Debug, Clone)]
+pub struct Eviction {
+    pub manifest: ManifestSummary,
+    pub reason: EvictReason,
+}
+
+/// What a retention pass would do. Built by `plan`, carried out by `apply`.
+#[derive(Debug, Default)]
+pub struct RetentionPlan {
+    pub evict: Vec<Eviction>,
+    /// Manifests the policy would have evicted but a keep rule spared.
+    pub protected: Vec<(ManifestSummary, Protection)>,
+    pub total_bytes: u64,
+}
+
+impl RetentionPlan {
+    pub fn freed_bytes(&self) -> u64 {
+        self.evict.iter().map(|e| e.manifest.size).sum()
+    }
+}
+
+/// Decides which manifests `policy` evicts. Keep rules always win over the
+/// size limit, so a catalog of protected manifests may stay over budget.
+pub fn plan(
+    store: &dyn CatalogStore,
+    index: &Index,
+    policy: &RetentionConfig,
+    pinned: &HashSet<String>,
+    now: i64,
+) -> Result<RetentionPlan> {
+    // Oldest first, metadata only
+    let mut manifests = Vec::new();
+    let mut query = ManifestQuery { limit: Some(500), ..Default::default() };
+    loop {
+        let page = store.query_manifest_summaries(&query)?;
+        let Some(last) = page.last() else { break };
+        query.after = Some(ManifestCursor { timestamp: last.timestamp, cid: last.cid.clone() });
+        manifests.extend(page);
+    }
+
+    let mut plan = RetentionPlan {
+        total_bytes: manifests.iter().map(|m| m.size).sum(),
+        ..Default::default()
+    };
+    // Namespaces with their own limits are budgeted separately; `None`
+    // collects everything under the global limits. Each bucket stays oldest first.
+    let mut buckets: BTreeMap<Option<String>, Vec<ManifestSummary>> = BTreeMap::new();
+    for manifest in manifests {
+        let key = policy.namespaces.contains_key(&manifest.namespace).then(|| manifest.namespace.clone());
+        buckets.entry(key).or_default().push(manifest);
+    }
+    for (namespace, manifests) in buckets {
+        let (max_age_days, max_catalog_mb) = match namespace.as_ref().map(|ns| &policy.namespaces[ns]) {
+            Some(limits) => (limits.max_age_days, limits.max_catalog_mb),
+            None => (policy.max_age_days, policy.max_catalog_mb),
+        };
+        let age_cutoff = max_age_days.map(|days| now - days as i64 * 86400);
+        let size_limit = max_catalog_mb.map(|mb| mb * 1024 * 1024);
+        let mut remaining: u64 = manifests.iter().map(|m| m.size).sum();
+        for manifest in manifests {
+            let reason = if age_cutoff.map_or(false, |cutoff| manifest.timestamp < cutoff) {
+                EvictReason::Expired
+            } else if size_limit.map_or(false, |limit| remaining > limit) {
+                EvictReason::OverSize
+            } else {
+                continue;
+            };
+            if policy.keep_pinned && pinned.contains(&manifest.cid) {
+                plan.protected.push((manifest, Protection::Pinned));
+            } else if policy.keep_finalized && store.get_provenance(&manifest.cid)?.map_or(false, |p| p.finalized) {
+                plan.protected.push((manifest, Protection::Finalized));
+            } else {
+                remaining -= manifest.size;
+                plan.evict.push(Eviction { manifest, reason });
+            }
+        }
+    }
+
+    if policy.keep_lineage_ancestors {
+        // Rescuing an ancestor makes it a survivor whose own ancestors must
+        // stay too, so repeat until nothing changes
+        loop {
+            let evicting: HashSet<&str> = plan.evict.iter().map(|e| e.manifest.cid.as_str()).collect();
+            let mut ancestors = HashSet::new();
+            for cid in index.cids() {
+                if !evicting.contains(cid.as_str()) {
+                    ancestors.extend(index.lineage(cid).into_iter().filter(|c| c != cid));
+                }
+            }
+            let (rescued, evict): (Vec<_>, Vec<_>) =
+                plan.evict.drain(..).partition(|e| ancestors.contains(&e.manifest.cid));
+            plan.evict = evict;
+            if rescued.is_empty() {
+                break;
+            }
+            plan.protected.extend(rescued.into_iter().map(|e| (e.manifest, Protection::LineageAncestor)));
+        }
+    }
+    Ok(plan)
+}
+
+/// Evicts everything in `plan`. Each eviction leaves a local tombstone and
+/// takes the manifest's provenance, attestations and blob with it.
+pub fn apply(store: &dyn CatalogStore, plan: &RetentionPlan, now: i64) -> Result<usize> {
+    for eviction in &plan.evict {
+        store.apply_tombstone(&Tombstone {
+            cid: eviction.manifest.cid.clone(),
+            deleted_at: now,
+            reason: TombstoneReason::Evicted,
+        })?;
+    }
+    if !plan.evict.is_empty() {
+        warn!("Evicted {} manifests ({} bytes)", plan.evict.len(), plan.freed_bytes());
+    }
+    Ok(plan.evict.len())
+}
+
+/// One full retention pass: plan, then (unless `dry_run`) evict, unpin and
+/// expire old tombstones. Without `ipfs`, nothing counts as pinned.
+pub async fn run(
+    storage: &AsyncStorage,
+    ipfs: Option<&IpfsCache>,
+    policy: &RetentionConfig,
+    storage_config: &StorageConfig,
+    dry_run: bool,
+) -> Result<RetentionPlan> {
+    let now = chrono::Utc::now().timestamp();
+    let plan = if !has_limits(policy) {
+        RetentionPlan::default()
+    } else {
+        // Refuse to guess: if pins can't be listed, keep-if-pinned can't be honoured
+        let pinned = match ipfs {
+            Some(ipfs) if policy.keep_pinned => ipfs.pinned().await?,
+            _ => HashSet::new(),
+        };
+        let plan_policy = policy.clone();
+        storage
+            .call(move |store| {
+                let index = if plan_policy.keep_lineage_ancestors { Index::load(store)? } else { Index::new() };
+                plan(store, &index, &plan_policy, &pinned, now)
+            })
+            .await?
+    };
+    if dry_run {
+        return Ok(plan);
+    }
+
+    let plan = storage
+        .call(move |store| {
+            apply(store, &plan, now)?;
+            Ok(plan)
+        })
+        .await?;
+    if let Some(ipfs) = ipfs {
+        for eviction in &plan.evict {
+            if let Err(e) = ipfs.unpin(&eviction.manifest.cid).await {
+                warn!("Failed to unpin {}: {}", eviction.manifest.cid, e);
+            }
+        }
+    }
+    let evicted_before = now - storage_config.evicted_tombstone_days as i64 * 86400;
+    let retracted_before = now - storage_config.retracted_tombstone_days as i64 * 86400;
+    storage
+        .call(move |store| store.prune_tombstones(evicted_before, retracted_before))
+        .await?;
+    Ok(plan)
+}
+
+/// Whether `policy` can evict anything at all.
+pub fn has_limits(policy: &RetentionConfig) -> bool {
+    policy.max_age_days.is_some()
+        || policy.max_catalog_mb.is_some()
+        || policy.namespaces.values().any(|ns| ns.max_age_days.is_some() || ns.max_catalog_mb.is_some())
+}
+
+/// Runs retention every `janitor_interval_minutes` in the daemon.
+pub fn spawn_janitor(
+    storage: AsyncStorage,
+    ipfs: Option<Arc<IpfsCache>>,
+    policy: RetentionConfig,
+    storage_config: StorageConfig,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(policy.janitor_interval_minutes.max(1) * 60));
+        loop {
+            interval.tick().await;
+            match run(&storage, ipfs.as_deref(), &policy, &storage_config, false).await {
+                Ok(plan) => info!(
+                    "Retention pass: evicted {}, protected {}",
+                    plan.evict.len(),
+                    plan.protected.len()
+                ),
+                Err(e) => error!("Retention pass failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use crate::storage::Provenance;
+    use crate::store::MemoryStore;
+
+    #[test]
+    fn test_plan_respects_keep_rules() -> Result<()> {
+        let store = MemoryStore::new();
+        for (cid, timestamp) in [("old_finalized", 10), ("old_pinned", 20), ("old", 30), ("new", 1000)] {
+            store.insert_manifest(&Manifest { cid: cid.to_string(), data: vec![0; 100], timestamp })?;
+        }
+        let provenance = Provenance { finalized: true, attestation_count: 0, tx_signature: "sig".to_string(), slot: 1, anchored_at: None };
+        store.cache_provenance("old_finalized", &provenance)?;
+        let pinned = HashSet::from(["old_pinned".to_string()]);
+        let index = Index::load(&store)?;
+
+        let policy = RetentionConfig { max_age_days: Some(1), ..Default::default() };
+        let by_age = plan(&store, &index, &policy, &pinned, 86400 + 500)?;
+        let evicted: Vec<&str> = by_age.evict.iter().map(|e| e.manifest.cid.as_str()).collect();
+        assert_eq!(evicted, vec!["old"]);
+        assert_eq!(by_age.protected.len(), 2);
+
+        // A zero budget evicts everything unprotected, oldest first
+        let policy = RetentionConfig { max_catalog_mb: Some(0), keep_finalized: false, ..Default::default() };
+        let by_size = plan(&store, &index, &policy, &pinned, 0)?;
+        let evicted: Vec<&str> = by_size.evict.iter().map(|e| e.manifest.cid.as_str()).collect();
+        assert_eq!(evicted, vec!["old_finalized", "old", "new"]);
+        assert_eq!(by_size.evict[0].reason, EvictReason::OverSize);
+
+        assert_eq!(apply(&store, &by_size, 0)?, 3);
+        assert!(store.get_provenance("old_finalized")?.is_none());
+        assert_eq!(store.get_tombstone("old")?.unwrap().reason, TombstoneReason::Evicted);
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use std::str::FromStr;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use tracing::{info, warn};
+
+use crate::envelope;
+use crate::manifest::{Manifest, ManifestDocument};
+use crate::storage::{Tombstone, TombstoneReason};
+use crate::store::CatalogStore;
+
+/// Most records one peer may have waiting for manifests this node hasn't
+/// seen. Anyone can sign a retraction for a made-up CID, so past this
+/// further ones from that peer are dropped instead of stored.
+pub const MAX_PENDING_PER_SOURCE: usize = 1000;
+
+/// A creator's signed statement that a manifest is withdrawn (leaked data,
+/// a broken model, ...). It travels between peers like a manifest and
+/// outlives it: the record is what keeps the CID from coming back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Retraction {
+    pub cid: String,
+    /// Creator of the retracted manifest; no other key can retract it.
+    pub creator: String,
+    pub reason: String,
+    pub retracted_at: i64,
+    /// Base58 Ed25519 signature by `creator` over `signing_message()`.
+    pub signature: String,
+}
+
+/// What a retracted manifest was about, kept with its record once the
+/// manifest is gone so sync only offers the retraction to peers that
+/// replicate such manifests. The creator is the record's own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetractedScope {
+    pub namespace: String,
+    pub kind: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl RetractedScope {
+    pub fn of(document: &ManifestDocument) -> Self {
+        Self {
+            namespace: document.namespace().to_string(),
+            kind: document.kind.clone(),
+            tags: document.tags.clone(),
+        }
+    }
+}
+
+/// What applying a retraction to a catalog did.
+#[derive(Debug)]
+pub enum RetractionOutcome {
+    /// The manifest was removed and tombstoned; it is returned for unpinning.
+    Applied(Manifest),
+    /// The manifest isn't in the catalog. The record is kept and applied if
+    /// the manifest arrives later.
+    Pending,
+    /// The stored manifest has another creator; the record was ignored.
+    NotCreator,
+    /// The manifest isn't in the catalog and the sending peer already has
+    /// `MAX_PENDING_PER_SOURCE` records waiting; the record was dropped.
+    TooManyPending,
+}
+
+impl Retraction {
+    /// A retraction of `cid` signed by `creator`.
+    pub fn new(cid: &str, reason: &str, creator: &Keypair, now: i64) -> Self {
+        let mut retraction = Self {
+            cid: cid.to_string(),
+            creator: creator.pubkey().to_string(),
+            reason: reason.to_string(),
+            retracted_at: now,
+            signature: String::new(),
+        };
+        retraction.signature = creator.sign_message(&retraction.signing_message()).to_string();
+        retraction
+    }
+
+    pub fn signing_message(&self) -> Vec<u8> {
+        format!(
+            "neuroswarm-retraction:{}:{}:{}:{}",
+            self.cid, self.creator, self.retracted_at, self.reason
+        )
+        .into_bytes()
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let creator = Pubkey::from_str(&self.creator)?;
+        let signature = Signature::from_str(&self.signature)?;
+        if !signature.verify(creator.as_ref(), &self.signing_message()) {
+            return Err(anyhow!("retraction of {} is not signed by {}", self.cid, self.creator));
+        }
+        Ok(())
+    }
+
+    /// The local tombstone standing in for the removed manifest.
+    pub fn tombstone(&self, deleted_at: i64) -> Tombstone {
+        Tombstone {
+            cid: self.cid.clone(),
+            deleted_at,
+            reason: TombstoneReason::Retracted,
+        }
+    }
+}
+
+/// Records a retraction whose signature has been checked and, if its
+/// manifest is stored and was published by the same creator, removes it.
+/// `source` is the peer it came from, or `None` when made on this node.
+/// Records waiting for their manifest expire with retraction tombstones
+/// (see `CatalogStore::prune_tombstones`).
+pub fn apply(store: &dyn CatalogStore, retraction: &Retraction, source: Option<&str>) -> Result<RetractionOutcome> {
+    let manifest = store.get_manifest(&retraction.cid)?;
+    let document = manifest.as_ref().and_then(|m| m.document().ok());
+    if manifest.is_some() && document.as_ref().map(|d| d.creator.as_str()) != Some(retraction.creator.as_str()) {
+        warn!("Ignoring retraction of {} by {}: not its creator", retraction.cid, retraction.creator);
+        return Ok(RetractionOutcome::NotCreator);
+    }
+    if let (None, Some(source)) = (&manifest, source) {
+        if store.count_pending_retractions(source)? >= MAX_PENDING_PER_SOURCE {
+            warn!("Dropping retraction of {} from {}: too many pending", retraction.cid, source);
+            return Ok(RetractionOutcome::TooManyPending);
+        }
+    }
+    store.record_retraction(retraction, document.as_ref().map(RetractedScope::of).as_ref(), source)?;
+    let Some(manifest) = manifest else { return Ok(RetractionOutcome::Pending) };
+    store.apply_tombstone(&retraction.tombstone(chrono::Utc::now().timestamp()))?;
+    info!("Retracted {}: {}", retraction.cid, retraction.reason);
+    Ok(RetractionOutcome::Applied(manifest))
+}
+
+/// Everything a node may have pinned for `manifest`: the manifest itself and
+/// its artifacts, if the document is readable. Private manifests are opened
+/// with `keypair` when it is a recipient.
+pub fn pinned_cids(manifest: &Manifest, keypair: Option<&Keypair>) -> Vec<String> {
+    let mut cids = vec![manifest.cid.clone()];
+    let document = manifest.document().ok().and_then(|document| match (&document.encrypted, keypair) {
+        (None, _) => Some(document),
+        (Some(_), Some(keypair)) => envelope::open(&document, keypair).ok(),
+        (Some(_), None) => None,
+    });
+    if let Some(document) = document {
+        cids.extend(document.artifacts.into_iter().map(|a| a.cid));
+    }
+    cids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn manifest(creator: &Keypair) -> Manifest {
+        let document = serde_json::json!({
+            "schema_version": 1,
+            "creator": creator.pubkey().to_string(),
+            "node_id": "node1",
+            "created_at": 1
+        });
+        Manifest { cid: "cid_a".to_string(), data: document.to_string().into_bytes(), timestamp: 1 }
+    }
+
+    #[test]
+    fn test_only_creator_retracts() -> Result<()> {
+        let creator = Keypair::new();
+        let store = MemoryStore::new();
+        store.insert_manifest(&manifest(&creator))?;
+
+        let mut forged = Retraction::new("cid_a", "leaked", &Keypair::new(), 10);
+        assert!(forged.verify().is_ok());
+        assert!(matches!(apply(&store, &forged, None)?, RetractionOutcome::NotCreator));
+        forged.creator = creator.pubkey().to_string();
+        assert!(forged.verify().is_err());
+
+        let retraction = Retraction::new("cid_a", "leaked", &creator, 10);
+        retraction.verify()?;
+        assert!(matches!(apply(&store, &retraction, None)?, RetractionOutcome::Applied(_)));
+        assert!(store.get_manifest("cid_a")?.is_none());
+        assert_eq!(store.get_retractions("cid_a")?, vec![retraction]);
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use jsonschema::JSONSchema;
+use serde_json::Value;
+use tracing::info;
+
+use crate::config::SchemaConfig;
+use crate::manifest::{ManifestDocument, ManifestError};
+
+const BUILTIN: &[(&str, &str)] = &[
+    ("model", include_str!("../schemas/model.json")),
+    ("dataset", include_str!("../schemas/dataset.json")),
+    ("evaluation", include_str!("../schemas/evaluation.json")),
+];
+
+/// JSON Schemas for manifest content, keyed by the document's `type`.
+///
+/// `ManifestDocument::validate` covers the fields every manifest shares;
+/// the registry adds the per-type requirements on top (a model names its
+/// framework, a dataset its license, ...).
+pub struct SchemaRegistry {
+    schemas: BTreeMap<String, JSONSchema>,
+    allow_unknown_types: bool,
+}
+
+impl SchemaRegistry {
+    /// The schemas shipped with the node.
+    pub fn builtin() -> Self {
+        let mut registry = Self {
+            schemas: BTreeMap::new(),
+            allow_unknown_types: true,
+        };
+        for (kind, schema) in BUILTIN {
+            let schema: Value = serde_json::from_str(schema).expect("built-in schemas are valid JSON");
+            registry.register(kind, &schema).expect("built-in schemas compile");
+        }
+        registry
+    }
+
+    /// Built-in schemas plus every `<type>.json` in `config.dir`.
+    pub fn load(config: &SchemaConfig) -> Result<Self> {
+        let mut registry = Self::builtin();
+        registry.allow_unknown_types = config.allow_unknown_types;
+        if let Some(dir) = &config.dir {
+            for entry in std::fs::read_dir(dir).with_context(|| format!("reading schema dir {}", dir))? {
+                let path = entry?.path();
+                if path.extension().map_or(true, |ext| ext != "json") {
+                    continue;
+                }
+                registry.register_file(&path)?;
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Adds or replaces the schema for `kind`.
+    pub fn register(&mut self, kind: &str, schema: &Value) -> Result<()> {
+        let compiled = JSONSchema::compile(schema).map_err(|e| anyhow!("schema for `{}` is invalid: {}", kind, e))?;
+        self.schemas.insert(kind.to_string(), compiled);
+        Ok(())
+    }
+
+    fn register_file(&mut self, path: &Path) -> Result<()> {
+        let kind = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow!("bad schema file name {}", path.display()))?;
+        let schema: Value = serde_json::from_slice(&std::fs::read(path)?)
+            .with_context(|| format!("parsing {}", path.display()))?;
+        self.register(kind, &schema)?;
+        info!("Loaded manifest schema `{}` from {}", kind, path.display());
+        Ok(())
+    }
+
+    pub fn kinds(&self) -> impl Iterator<Item = &String> {
+        self.schemas.keys()
+    }
+
+    /// Checks `document` against the schema for its `type`. Untyped
+    /// documents pass, and so do sealed ones: their content can't be read,
+    /// so the publisher validates it before sealing.
+    pub fn validate(&self, document: &ManifestDocument) -> Result<(), ManifestError> {
+        let Some(kind) = &document.kind else { return Ok(()) };
+        if document.encrypted.is_some() {
+            return Ok(());
+        }
+        let Some(schema) = self.schemas.get(kind) else {
+            return if self.allow_unknown_types {
+                Ok(())
+            } else {
+                Err(ManifestError::UnknownType(kind.clone()))
+            };
+        };
+        let instance = serde_json::to_value(document).expect("manifest documents always serialize");
+        if let Err(errors) = schema.validate(&instance) {
+            let errors = errors
+                .map(|e| {
+                    let path = e.instance_path.to_string();
+                    if path.is_empty() {
+                        e.to_string()
+                    } else {
+                        format!("{}: {}", path, e)
+                    }
+                })
+                .collect();
+            return Err(ManifestError::Schema { kind: kind.clone(), errors });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(kind: &str, extra: Value) -> ManifestDocument {
+        let mut data = serde_json::json!({
+            "schema_version": 1,
+            "type": kind,
+            "creator": "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS",
+            "node_id": "node1",
+            "created_at": 1700000000
+        });
+        data.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        ManifestDocument::parse(data.to_string().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_validate_by_type() {
+        let mut registry = SchemaRegistry::builtin();
+        let weights = serde_json::json!([{"name": "weights", "cid": "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n"}]);
+
+        let model = document("model", serde_json::json!({"framework": "pytorch", "artifacts": weights}));
+        assert_eq!(registry.validate(&model), Ok(()));
+
+        let incomplete = document("model", serde_json::json!({"artifacts": weights}));
+        assert!(matches!(
+            registry.validate(&incomplete),
+            Err(ManifestError::Schema { kind, errors }) if kind == "model" && errors.len() == 1
+        ));
+
+        let custom = document("benchmark", serde_json::json!({}));
+        assert_eq!(registry.validate(&custom), Ok(()));
+        registry.allow_unknown_types = false;
+        assert_eq!(registry.validate(&custom), Err(ManifestError::UnknownType("benchmark".to_string())));
+        registry
+            .register("benchmark", &serde_json::json!({"required": ["suite"]}))
+            .unwrap();
+        assert!(registry.validate(&custom).is_err());
+    }
+}
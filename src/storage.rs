@@ -1,137 +1,1209 @@
-use rusqlite::{params, Connection, Result};
+use anyhow::{anyhow, Result};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
+use std::time::Duration;
 use tracing::{info, warn};
 
+use crate::blobs::BlobStore;
+use crate::ipfs;
+use crate::manifest::{Manifest, ManifestDocument, DEFAULT_NAMESPACE};
+use crate::migrations;
+use crate::retraction::{RetractedScope, Retraction};
+use crate::store::CatalogStore;
+
+/// Columns needed to rebuild a `Manifest` from a row; see `Storage::load_rows`.
+const MANIFEST_COLUMNS: &str = "cid, data, blob_key, timestamp";
+
 pub struct Storage {
     conn: Connection,
+    /// Payload store. When absent (in-memory catalogs) data stays inline.
+    blobs: Option<BlobStore>,
+}
+
+/// Catalog metadata for a manifest, without loading its data BLOB.
+#[derive(Debug, Clone)]
+pub struct ManifestSummary {
+    pub cid: String,
+    pub timestamp: i64,
+    pub size: u64,
+    pub namespace: String,
+}
+
+/// Keyset position in the catalog's `(timestamp, cid)` order. Pass the last
+/// row's cursor back to fetch the next page.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestCursor {
+    pub timestamp: i64,
+    pub cid: String,
+}
+
+/// Filter and page bounds for manifest queries. Results are ordered by
+/// `(timestamp, cid)`.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestQuery {
+    /// Exclusive lower bound on timestamp.
+    pub since: Option<i64>,
+    /// Inclusive upper bound on timestamp.
+    pub until: Option<i64>,
+    /// Resume after this row.
+    pub after: Option<ManifestCursor>,
+    /// Maximum rows to return; `None` for no limit.
+    pub limit: Option<usize>,
+    /// Only manifests in these namespaces; `None` for all of them.
+    pub namespaces: Option<Vec<String>>,
+    /// Only manifests signed by one of these creators.
+    pub creators: Option<Vec<String>>,
+    /// Only manifests carrying at least one of these tags.
+    pub tags: Option<Vec<String>>,
+    /// Only manifests of these `type`s.
+    pub types: Option<Vec<String>>,
+}
+
+impl ManifestQuery {
+    /// Whether a manifest at `(timestamp, cid)` falls inside the query's
+    /// range and after its cursor. Ignores `limit`.
+    pub fn matches(&self, timestamp: i64, cid: &str) -> bool {
+        self.since.map_or(true, |since| timestamp > since)
+            && self.until.map_or(true, |until| timestamp <= until)
+            && self
+                .after
+                .as_ref()
+                .map_or(true, |after| (timestamp, cid) > (after.timestamp, after.cid.as_str()))
+    }
+
+    /// Whether a manifest with this parsed document passes the namespace,
+    /// creator, tag and type filters. Unparsed manifests are in the default
+    /// namespace and have no creator, tags or type.
+    pub fn matches_document(&self, document: Option<&ManifestDocument>) -> bool {
+        self.matches_fields(
+            document.map_or(DEFAULT_NAMESPACE, |d| d.namespace()),
+            document.map(|d| d.creator.as_str()),
+            document.and_then(|d| d.kind.as_deref()),
+            document.map_or(&[][..], |d| d.tags.as_slice()),
+        )
+    }
+
+    /// Whether a retraction by `creator` passes the filters, judged by the
+    /// scope of the manifest it removed. Without a scope nothing but the
+    /// creator is known, so it only passes queries that don't restrict
+    /// namespaces, types or tags.
+    pub fn matches_retraction(&self, creator: &str, scope: Option<&RetractedScope>) -> bool {
+        match scope {
+            Some(scope) => self.matches_fields(&scope.namespace, Some(creator), scope.kind.as_deref(), &scope.tags),
+            None => {
+                self.namespaces.is_none()
+                    && self.types.is_none()
+                    && self.tags.is_none()
+                    && self.creators.as_ref().map_or(true, |creators| creators.iter().any(|c| c == creator))
+            }
+        }
+    }
+
+    fn matches_fields(&self, namespace: &str, creator: Option<&str>, kind: Option<&str>, tags: &[String]) -> bool {
+        let any = |filter: &Option<Vec<String>>, value: Option<&str>| {
+            filter.as_ref().map_or(true, |values| value.map_or(false, |v| values.iter().any(|f| f == v)))
+        };
+        any(&self.namespaces, Some(namespace))
+            && any(&self.creators, creator)
+            && any(&self.types, kind)
+            && self.tags.as_ref().map_or(true, |wanted| tags.iter().any(|t| wanted.contains(t)))
+    }
+}
+
+/// The part of the catalog a node replicates: sent with `RequestCatalog`
+/// and configured under `replicate`. A manifest must match every non-empty
+/// list, and matches a list if any of its values does; empty lists match
+/// everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct CatalogFilter {
+    pub namespaces: Vec<String>,
+    pub creators: Vec<String>,
+    pub tags: Vec<String>,
+    pub types: Vec<String>,
+}
+
+impl CatalogFilter {
+    pub fn is_empty(&self) -> bool {
+        self.namespaces.is_empty() && self.creators.is_empty() && self.tags.is_empty() && self.types.is_empty()
+    }
+
+    /// Restricts `query` to the filter's non-empty lists.
+    pub fn apply(&self, query: &mut ManifestQuery) {
+        let some = |values: &Vec<String>| (!values.is_empty()).then(|| values.clone());
+        query.namespaces = some(&self.namespaces);
+        query.creators = some(&self.creators);
+        query.tags = some(&self.tags);
+        query.types = some(&self.types);
+    }
+
+    pub fn matches(&self, document: Option<&ManifestDocument>) -> bool {
+        let mut query = ManifestQuery::default();
+        self.apply(&mut query);
+        query.matches_document(document)
+    }
 }
 
-#[derive(Debug)]
-pub struct Manifest {
+/// Marker left behind when a manifest is removed, so stale peers can't
+/// re-import it through a later `RequestCatalog`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tombstone {
     pub cid: String,
-    pub data: Vec<u8>,
+    pub deleted_at: i64,
+    pub reason: TombstoneReason,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TombstoneReason {
+    /// Dropped from the local cache only. Never sent to peers.
+    Evicted,
+    /// Withdrawn by its creator. Only written for a verified `Retraction`
+    /// (see `retraction::apply`); sync exchanges the signed record, never
+    /// the tombstone.
+    Retracted,
+}
+
+impl TombstoneReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TombstoneReason::Evicted => "evicted",
+            TombstoneReason::Retracted => "retracted",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "retracted" => TombstoneReason::Retracted,
+            _ => TombstoneReason::Evicted,
+        }
+    }
+}
+
+/// A validator's signed confidence score for a manifest.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attestation {
+    pub manifest_cid: String,
+    /// Base58 validator public key.
+    pub validator: String,
+    /// Between 0 and 1 inclusive.
+    pub confidence: f64,
     pub timestamp: i64,
+    /// Base58 Ed25519 signature over `signing_message()`.
+    pub signature: String,
+}
+
+impl Attestation {
+    /// The confidence is signed as its IEEE 754 bits in hex, so signer and
+    /// verifier agree on the bytes whatever their float formatting.
+    pub fn signing_message(&self) -> Vec<u8> {
+        format!(
+            "neuroswarm-attestation:{}:{:016x}:{}",
+            self.manifest_cid,
+            self.confidence.to_bits(),
+            self.timestamp
+        )
+        .into_bytes()
+    }
+}
+
+/// Result of merging an incoming manifest into the catalog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeOutcome {
+    Inserted,
+    Unchanged,
+    TimestampUpdated,
+    /// Incoming data differs from the stored data for the same CID; the
+    /// stored copy was kept.
+    Conflict,
+}
+
+/// Audit record of a disagreement between a stored manifest and an
+/// incoming copy of the same CID.
+#[derive(Debug, Clone)]
+pub struct ManifestConflict {
+    pub cid: String,
+    pub source: String,
+    pub kind: ConflictKind,
+    pub existing_timestamp: i64,
+    pub incoming_timestamp: i64,
+    pub detected_at: i64,
+}
+
+/// Why a manifest was refused at ingest (bad signature, schema violation,
+/// failed anchor check, ...). Only the latest rejection per CID is kept, and
+/// it is cleared once the manifest is stored.
+#[derive(Debug, Clone)]
+pub struct ManifestRejection {
+    pub cid: String,
+    pub source: String,
+    pub reason: String,
+    pub rejected_at: i64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    DataMismatch,
+    Timestamp,
+}
+
+impl ConflictKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ConflictKind::DataMismatch => "data_mismatch",
+            ConflictKind::Timestamp => "timestamp",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "data_mismatch" => ConflictKind::DataMismatch,
+            _ => ConflictKind::Timestamp,
+        }
+    }
+}
+
+/// Persisted per-peer sync progress, so `nsd status` can report on a
+/// running daemon and sync can resume after a restart.
+#[derive(Debug, Clone, Default)]
+pub struct SyncState {
+    pub peer: String,
+    /// Start time of the last catalog sync that ran to completion.
+    pub last_sync: i64,
+    pub started_at: i64,
+    pub pages_fetched: u64,
+    pub manifests_received: u64,
+    pub pending_verification: u64,
+    /// Catalog size the peer advertised for the current request.
+    pub advertised_total: Option<u64>,
+    pub complete: bool,
+    pub updated_at: i64,
+}
+
+impl SyncState {
+    /// Estimated manifests still to arrive, from the peer-advertised size.
+    pub fn remaining(&self) -> Option<u64> {
+        self.advertised_total.map(|total| total.saturating_sub(self.manifests_received))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Provenance {
     pub finalized: bool,
     pub attestation_count: u64,
     pub tx_signature: String,
     pub slot: u64,
+    /// Block time of the anchoring slot, when the RPC node reported it.
+    #[serde(default)]
+    pub anchored_at: Option<i64>,
+}
+
+/// A provenance cache entry and when it was fetched from the chain.
+#[derive(Debug, Clone)]
+pub struct CachedProvenance {
+    pub provenance: Provenance,
+    pub cached_at: i64,
 }
 
 impl Storage {
+    /// Opens `path`, keeping payloads in a blob directory alongside it
+    /// (`catalog.db` -> `catalog.blobs/`).
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let conn = Connection::open(path)?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS manifests (
-                cid TEXT PRIMARY KEY,
-                data BLOB,
-                timestamp INTEGER
-            )",
-            [],
-        )?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS provenance (
-                cid TEXT PRIMARY KEY,
-                finalized BOOLEAN,
-                attestation_count INTEGER,
-                tx_signature TEXT,
-                slot INTEGER,
-                cached_at INTEGER,
-                FOREIGN KEY(cid) REFERENCES manifests(cid)
-            )",
-            [],
+        let conn = Connection::open(path.as_ref())?;
+        // WAL lets CLI commands read while the daemon writes
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        migrations::migrate(&conn, false)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let blobs = BlobStore::new(path.as_ref().with_extension("blobs"))?;
+        let storage = Self { conn, blobs: Some(blobs) };
+        storage.backfill_metadata()?;
+        Ok(storage)
+    }
+
+    /// A throwaway SQLite catalog, mainly for tests.
+    pub fn in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        migrations::migrate(&conn, false)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        Ok(Self { conn, blobs: None })
+    }
+
+    /// Merges a manifest received from `source` into the catalog.
+    ///
+    /// Data is immutable per CID: a copy whose bytes differ from the stored
+    /// ones is rejected and recorded as a conflict. The timestamp resolves to
+    /// the on-chain anchor time once known (`anchored_at`), otherwise to the
+    /// earliest timestamp seen. Every disagreement is recorded for audit.
+    pub fn merge_manifest(&self, manifest: &Manifest, source: &str, anchored_at: Option<i64>) -> Result<MergeOutcome> {
+        let incoming_key = ipfs::compute_cid(&manifest.data);
+        // Write lock up front: the blob and the row referencing it land
+        // together, so no other process's `release_blobs` sees one without
+        // the other
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        let existing: Option<(Option<String>, Option<Vec<u8>>, i64, bool)> = tx
+            .query_row(
+                "SELECT blob_key, data, timestamp, timestamp_anchored FROM manifests WHERE cid = ?1",
+                params![manifest.cid],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+
+        let outcome = match existing {
+            None => {
+                // Payload goes to the blob store; the row keeps metadata and the key
+                let inline = match &self.blobs {
+                    Some(blobs) => {
+                        blobs.put(&manifest.data)?;
+                        None
+                    }
+                    None => Some(&manifest.data),
+                };
+                tx.execute(
+                    "INSERT INTO manifests (cid, data, blob_key, size, timestamp, timestamp_anchored)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        manifest.cid,
+                        inline,
+                        incoming_key,
+                        manifest.data.len() as i64,
+                        anchored_at.unwrap_or(manifest.timestamp),
+                        anchored_at.is_some()
+                    ],
+                )?;
+                write_metadata(&tx, manifest)?;
+                tx.execute("DELETE FROM manifest_rejections WHERE cid = ?1", params![manifest.cid])?;
+                info!("Inserted manifest: {}", manifest.cid);
+                MergeOutcome::Inserted
+            }
+            Some((blob_key, data, timestamp, anchored)) => {
+                // Legacy rows predate blob_key; hash their inline data instead
+                let stored_key = blob_key.or_else(|| data.map(|d| ipfs::compute_cid(&d)));
+                if stored_key.as_deref() != Some(incoming_key.as_str()) {
+                    record_conflict(&tx, &manifest.cid, source, ConflictKind::DataMismatch, timestamp, manifest.timestamp)?;
+                    warn!("Rejected manifest {} from {}: data differs from stored copy", manifest.cid, source);
+                    MergeOutcome::Conflict
+                } else {
+                    if manifest.timestamp != timestamp {
+                        record_conflict(&tx, &manifest.cid, source, ConflictKind::Timestamp, timestamp, manifest.timestamp)?;
+                    }
+                    let (resolved, resolved_anchored) = resolve_timestamp(timestamp, anchored, manifest.timestamp, anchored_at);
+                    if resolved != timestamp || resolved_anchored != anchored {
+                        tx.execute(
+                            "UPDATE manifests SET timestamp = ?2, timestamp_anchored = ?3 WHERE cid = ?1",
+                            params![manifest.cid, resolved, resolved_anchored],
+                        )?;
+                        info!("Resolved timestamp of {} from {} to {}", manifest.cid, timestamp, resolved);
+                        MergeOutcome::TimestampUpdated
+                    } else {
+                        MergeOutcome::Unchanged
+                    }
+                }
+            }
+        };
+        tx.commit()?;
+        Ok(outcome)
+    }
+
+    pub fn list_conflicts(&self, cid: Option<&str>) -> Result<Vec<ManifestConflict>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT cid, source, kind, existing_timestamp, incoming_timestamp, detected_at
+             FROM manifest_conflicts WHERE ?1 IS NULL OR cid = ?1 ORDER BY detected_at",
         )?;
-        Ok(Self { conn })
+        let rows = stmt.query_map(params![cid], |row| {
+            Ok(ManifestConflict {
+                cid: row.get(0)?,
+                source: row.get(1)?,
+                kind: ConflictKind::parse(&row.get::<_, String>(2)?),
+                existing_timestamp: row.get(3)?,
+                incoming_timestamp: row.get(4)?,
+                detected_at: row.get(5)?,
+            })
+        })?;
+        let mut conflicts = Vec::new();
+        for conflict in rows {
+            conflicts.push(conflict?);
+        }
+        Ok(conflicts)
     }
 
-    pub fn insert_manifest(&self, manifest: &Manifest) -> Result<()> {
+    pub fn record_rejection(&self, rejection: &ManifestRejection) -> Result<()> {
         self.conn.execute(
-            "INSERT OR REPLACE INTO manifests (cid, data, timestamp) VALUES (?1, ?2, ?3)",
-            params![manifest.cid, manifest.data, manifest.timestamp],
+            "INSERT INTO manifest_rejections (cid, source, reason, rejected_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(cid) DO UPDATE SET
+                source = excluded.source, reason = excluded.reason, rejected_at = excluded.rejected_at",
+            params![rejection.cid, rejection.source, rejection.reason, rejection.rejected_at],
         )?;
-        info!("Inserted manifest: {}", manifest.cid);
         Ok(())
     }
 
-    pub fn get_manifest(&self, cid: &str) -> Result<Option<Manifest>> {
-        let mut stmt = self.conn.prepare("SELECT cid, data, timestamp FROM manifests WHERE cid = ?1")?;
-        let mut rows = stmt.query_map(params![cid], |row| {
-            Ok(Manifest {
+    pub fn list_rejections(&self, cid: Option<&str>) -> Result<Vec<ManifestRejection>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT cid, source, reason, rejected_at FROM manifest_rejections
+             WHERE ?1 IS NULL OR cid = ?1 ORDER BY rejected_at",
+        )?;
+        let rows = stmt.query_map(params![cid], |row| {
+            Ok(ManifestRejection {
                 cid: row.get(0)?,
-                data: row.get(1)?,
-                timestamp: row.get(2)?,
+                source: row.get(1)?,
+                reason: row.get(2)?,
+                rejected_at: row.get(3)?,
             })
         })?;
-        if let Some(manifest) = rows.next() {
-            Ok(Some(manifest?))
-        } else {
-            Ok(None)
+        let mut rejections = Vec::new();
+        for rejection in rows {
+            rejections.push(rejection?);
         }
+        Ok(rejections)
+    }
+
+    pub fn get_manifest(&self, cid: &str) -> Result<Option<Manifest>> {
+        let rows = self.load_rows(&format!("SELECT {} FROM manifests WHERE cid = ?1", MANIFEST_COLUMNS), vec![Value::Text(cid.to_string())])?;
+        Ok(rows.into_iter().next())
     }
 
     pub fn list_manifests(&self) -> Result<Vec<Manifest>> {
-        let mut stmt = self.conn.prepare("SELECT cid, data, timestamp FROM manifests")?;
-        let rows = stmt.query_map([], |row| {
-            Ok(Manifest {
-                cid: row.get(0)?,
-                data: row.get(1)?,
-                timestamp: row.get(2)?,
-            })
+        self.load_rows(&format!("SELECT {} FROM manifests", MANIFEST_COLUMNS), Vec::new())
+    }
+
+    pub fn query_manifests(&self, query: &ManifestQuery) -> Result<Vec<Manifest>> {
+        let (filter, values) = manifest_filter(query, true);
+        self.load_rows(&format!("SELECT {} FROM manifests{}", MANIFEST_COLUMNS, filter), values)
+    }
+
+    /// Runs a `MANIFEST_COLUMNS` query and resolves each row's payload,
+    /// inline or from the blob store.
+    fn load_rows(&self, sql: &str, values: Vec<Value>) -> Result<Vec<Manifest>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<Vec<u8>>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
         })?;
         let mut manifests = Vec::new();
-        for manifest in rows {
-            manifests.push(manifest?);
+        for row in rows {
+            let (cid, data, blob_key, timestamp) = row?;
+            let data = match (data, blob_key, &self.blobs) {
+                (Some(data), _, _) => data,
+                (None, Some(key), Some(blobs)) => blobs
+                    .get(&key)?
+                    .ok_or_else(|| anyhow!("blob {} for manifest {} is missing", key, cid))?,
+                _ => return Err(anyhow!("manifest {} has no payload", cid)),
+            };
+            manifests.push(Manifest { cid, data, timestamp });
         }
         Ok(manifests)
     }
 
+    /// Like `query_manifests` but reads only metadata, never the data BLOB.
+    pub fn query_manifest_summaries(&self, query: &ManifestQuery) -> Result<Vec<ManifestSummary>> {
+        let (filter, values) = manifest_filter(query, true);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT cid, timestamp, COALESCE(size, length(data), 0), namespace FROM manifests{}",
+            filter
+        ))?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok(ManifestSummary {
+                cid: row.get(0)?,
+                timestamp: row.get(1)?,
+                size: row.get(2)?,
+                namespace: row.get::<_, Option<String>>(3)?.unwrap_or_else(|| DEFAULT_NAMESPACE.to_string()),
+            })
+        })?;
+        let mut summaries = Vec::new();
+        for summary in rows {
+            summaries.push(summary?);
+        }
+        Ok(summaries)
+    }
+
+    /// Counts manifests matching the query's range and cursor.
+    pub fn count_manifests(&self, query: &ManifestQuery) -> Result<u64> {
+        let (filter, values) = manifest_filter(query, false);
+        Ok(self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM manifests{}", filter),
+            params_from_iter(values),
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Evicts manifests older than `before_timestamp` from the local cache,
+    /// leaving an `Evicted` tombstone for each so sync doesn't pull them back.
     pub fn prune_old(&self, before_timestamp: i64) -> Result<usize> {
-        let count = self.conn.execute(
+        let now = chrono::Utc::now().timestamp();
+        let tx = self.conn.unchecked_transaction()?;
+        let keys = blob_keys(&tx, "timestamp < ?1", params![before_timestamp])?;
+        delete_dependents(&tx, "timestamp < ?1", params![before_timestamp])?;
+        tx.execute(
+            "INSERT OR IGNORE INTO tombstones (cid, deleted_at, reason)
+             SELECT cid, ?2, ?3 FROM manifests WHERE timestamp < ?1",
+            params![before_timestamp, now, TombstoneReason::Evicted.as_str()],
+        )?;
+        let count = tx.execute(
             "DELETE FROM manifests WHERE timestamp < ?1",
             params![before_timestamp],
         )?;
+        tx.commit()?;
+        self.release_blobs(keys)?;
         warn!("Pruned {} old manifests", count);
         Ok(count)
     }
 
+    /// Deletes the tombstoned manifest (if present) and stores the tombstone.
+    /// An existing retraction is never downgraded to an eviction.
+    pub fn apply_tombstone(&self, tombstone: &Tombstone) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let keys = blob_keys(&tx, "cid = ?1", params![tombstone.cid])?;
+        delete_dependents(&tx, "cid = ?1", params![tombstone.cid])?;
+        tx.execute("DELETE FROM manifests WHERE cid = ?1", params![tombstone.cid])?;
+        tx.execute(
+            "INSERT INTO tombstones (cid, deleted_at, reason) VALUES (?1, ?2, ?3)
+             ON CONFLICT(cid) DO UPDATE SET deleted_at = excluded.deleted_at, reason = excluded.reason
+             WHERE tombstones.reason != 'retracted'",
+            params![tombstone.cid, tombstone.deleted_at, tombstone.reason.as_str()],
+        )?;
+        tx.commit()?;
+        self.release_blobs(keys)?;
+        info!("Tombstoned manifest {} ({})", tombstone.cid, tombstone.reason.as_str());
+        Ok(())
+    }
+
+    /// Removes blobs no remaining manifest row references. Shared content
+    /// stays until its last manifest is gone. Holds the write lock
+    /// throughout, so a concurrent insert of the same content can't land
+    /// between the count and the delete.
+    fn release_blobs(&self, keys: Vec<String>) -> Result<()> {
+        let Some(blobs) = &self.blobs else { return Ok(()) };
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        for key in keys {
+            let refs: i64 = tx.query_row(
+                "SELECT COUNT(*) FROM manifests WHERE blob_key = ?1",
+                params![key],
+                |row| row.get(0),
+            )?;
+            if refs == 0 {
+                blobs.remove(&key)?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Moves payloads still stored inline (rows written before the blob store
+    /// existed) into the blob store. Returns how many rows were moved.
+    pub fn externalize_blobs(&self) -> Result<usize> {
+        let Some(blobs) = &self.blobs else { return Ok(0) };
+        let mut moved = 0;
+        loop {
+            let batch: Vec<(String, Vec<u8>)> = {
+                let mut stmt = self.conn.prepare("SELECT cid, data FROM manifests WHERE data IS NOT NULL LIMIT 100")?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect::<rusqlite::Result<_>>()?
+            };
+            if batch.is_empty() {
+                break;
+            }
+            // Blobs and the rows pointing at them commit under one write lock
+            let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+            for (cid, data) in batch {
+                let (key, _) = blobs.put(&data)?;
+                tx.execute(
+                    "UPDATE manifests SET data = NULL, blob_key = ?2, size = ?3 WHERE cid = ?1",
+                    params![cid, key, data.len() as i64],
+                )?;
+                moved += 1;
+            }
+            tx.commit()?;
+        }
+        if moved > 0 {
+            info!("Moved {} inline payloads to the blob store", moved);
+        }
+        Ok(moved)
+    }
+
+    pub fn get_tombstone(&self, cid: &str) -> Result<Option<Tombstone>> {
+        let mut stmt = self.conn.prepare("SELECT cid, deleted_at, reason FROM tombstones WHERE cid = ?1")?;
+        let mut rows = stmt.query_map(params![cid], |row| {
+            Ok(Tombstone {
+                cid: row.get(0)?,
+                deleted_at: row.get(1)?,
+                reason: TombstoneReason::parse(&row.get::<_, String>(2)?),
+            })
+        })?;
+        if let Some(tombstone) = rows.next() {
+            Ok(Some(tombstone?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Keeps a signed retraction, noting the peer it came from (`None` for
+    /// this node). The first record per creator and CID wins; a later call
+    /// only fills in the retracted manifest's scope if the stored record
+    /// lacks one.
+    pub fn record_retraction(
+        &self,
+        retraction: &Retraction,
+        scope: Option<&RetractedScope>,
+        source: Option<&str>,
+    ) -> Result<()> {
+        let tags = scope.map(|s| serde_json::to_string(&s.tags)).transpose()?;
+        self.conn.execute(
+            "INSERT INTO retractions
+                (cid, creator, reason, retracted_at, signature, received_at, namespace, kind, tags, source)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT(cid, creator) DO UPDATE
+                SET namespace = excluded.namespace, kind = excluded.kind, tags = excluded.tags
+                WHERE retractions.namespace IS NULL AND excluded.namespace IS NOT NULL",
+            params![
+                retraction.cid,
+                retraction.creator,
+                retraction.reason,
+                retraction.retracted_at,
+                retraction.signature,
+                chrono::Utc::now().timestamp(),
+                scope.map(|s| s.namespace.as_str()),
+                scope.and_then(|s| s.kind.as_deref()),
+                tags,
+                source
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records from `source` still waiting for a manifest this node hasn't
+    /// seen.
+    pub fn count_pending_retractions(&self, source: &str) -> Result<usize> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM retractions
+             WHERE source = ?1 AND cid NOT IN (SELECT cid FROM tombstones WHERE reason = 'retracted')",
+            params![source],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    pub fn get_retractions(&self, cid: &str) -> Result<Vec<Retraction>> {
+        self.load_retractions("WHERE cid = ?1", params![cid])
+    }
+
+    /// Signed records behind retraction tombstones written after
+    /// `query.since` that pass its filters (see
+    /// `ManifestQuery::matches_retraction`), for exchange with peers.
+    /// Records whose manifest was never seen here aren't passed on.
+    pub fn list_signed_retractions(&self, query: &ManifestQuery) -> Result<Vec<Retraction>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT cid, creator, reason, retracted_at, signature, namespace, kind, tags FROM retractions
+             WHERE cid IN (SELECT cid FROM tombstones WHERE reason = 'retracted' AND deleted_at > ?1)
+             ORDER BY retracted_at, cid",
+        )?;
+        let rows = stmt.query_map(params![query.since.unwrap_or(0)], |row| {
+            let retraction = Retraction {
+                cid: row.get(0)?,
+                creator: row.get(1)?,
+                reason: row.get(2)?,
+                retracted_at: row.get(3)?,
+                signature: row.get(4)?,
+            };
+            let namespace: Option<String> = row.get(5)?;
+            let kind: Option<String> = row.get(6)?;
+            let tags: Option<String> = row.get(7)?;
+            Ok((retraction, namespace, kind, tags))
+        })?;
+        let mut retractions = Vec::new();
+        for row in rows {
+            let (retraction, namespace, kind, tags) = row?;
+            let scope = match namespace {
+                Some(namespace) => Some(RetractedScope {
+                    namespace,
+                    kind,
+                    tags: tags.as_deref().map(serde_json::from_str::<Vec<String>>).transpose()?.unwrap_or_default(),
+                }),
+                None => None,
+            };
+            if query.matches_retraction(&retraction.creator, scope.as_ref()) {
+                retractions.push(retraction);
+            }
+        }
+        Ok(retractions)
+    }
+
+    fn load_retractions(&self, filter: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<Retraction>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT cid, creator, reason, retracted_at, signature FROM retractions {} ORDER BY retracted_at, cid",
+            filter
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(Retraction {
+                cid: row.get(0)?,
+                creator: row.get(1)?,
+                reason: row.get(2)?,
+                retracted_at: row.get(3)?,
+                signature: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Drops tombstones past their retention window. Evictions and
+    /// retractions have separate cutoffs since retractions must outlive
+    /// the slowest peer's resync interval. Signed records received before
+    /// the retraction cutoff go too unless their tombstone is still kept,
+    /// which covers both expired retractions and ones whose manifest never
+    /// arrived.
+    pub fn prune_tombstones(&self, evicted_before: i64, retracted_before: i64) -> Result<usize> {
+        let count = self.conn.execute(
+            "DELETE FROM tombstones
+             WHERE (reason = 'evicted' AND deleted_at < ?1)
+                OR (reason = 'retracted' AND deleted_at < ?2)",
+            params![evicted_before, retracted_before],
+        )?;
+        let records = self.conn.execute(
+            "DELETE FROM retractions
+             WHERE received_at < ?1
+               AND cid NOT IN (SELECT cid FROM tombstones WHERE reason = 'retracted')",
+            params![retracted_before],
+        )?;
+        info!("Pruned {} expired tombstones and {} retraction records", count, records);
+        Ok(count)
+    }
+
+    /// Stores an attestation, keeping only the newest one per validator.
+    /// Callers must verify the signature first. Attestations for manifests
+    /// not in the catalog are dropped.
+    pub fn insert_attestation(&self, attestation: &Attestation) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO attestations (manifest_cid, validator, confidence, timestamp, signature)
+             SELECT ?1, ?2, ?3, ?4, ?5 WHERE EXISTS (SELECT 1 FROM manifests WHERE cid = ?1)
+             ON CONFLICT(manifest_cid, validator) DO UPDATE SET
+                confidence = excluded.confidence,
+                timestamp = excluded.timestamp,
+                signature = excluded.signature
+             WHERE excluded.timestamp > attestations.timestamp",
+            params![
+                attestation.manifest_cid,
+                attestation.validator,
+                attestation.confidence,
+                attestation.timestamp,
+                attestation.signature
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_attestations(&self, cid: &str) -> Result<Vec<Attestation>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT manifest_cid, validator, confidence, timestamp, signature FROM attestations WHERE manifest_cid = ?1",
+        )?;
+        let rows = stmt.query_map(params![cid], |row| {
+            Ok(Attestation {
+                manifest_cid: row.get(0)?,
+                validator: row.get(1)?,
+                confidence: row.get(2)?,
+                timestamp: row.get(3)?,
+                signature: row.get(4)?,
+            })
+        })?;
+        let mut attestations = Vec::new();
+        for attestation in rows {
+            attestations.push(attestation?);
+        }
+        Ok(attestations)
+    }
+
+    /// Manifest count per namespace, by name.
+    pub fn list_namespaces(&self) -> Result<Vec<(String, u64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(namespace, ?1), COUNT(*) FROM manifests GROUP BY 1 ORDER BY 1",
+        )?;
+        let rows = stmt.query_map(params![DEFAULT_NAMESPACE], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Derives the document columns (namespace, creator, type, tags) of rows
+    /// written before schema v11. Runs on open; once every row has a
+    /// namespace it is a single indexed lookup.
+    pub fn backfill_metadata(&self) -> Result<usize> {
+        let mut filled = 0;
+        loop {
+            let batch = self.load_rows(
+                &format!("SELECT {} FROM manifests WHERE namespace IS NULL LIMIT 100", MANIFEST_COLUMNS),
+                Vec::new(),
+            )?;
+            if batch.is_empty() {
+                break;
+            }
+            let tx = self.conn.unchecked_transaction()?;
+            for manifest in batch {
+                write_metadata(&tx, &manifest)?;
+                filled += 1;
+            }
+            tx.commit()?;
+        }
+        if filled > 0 {
+            info!("Indexed document fields of {} manifests", filled);
+        }
+        Ok(filled)
+    }
+
+    /// The catalog's schema version; always the latest once opened.
+    pub fn schema_version(&self) -> Result<u32> {
+        Ok(migrations::current_version(&self.conn)?)
+    }
+
+    /// Returns `(manifest_count, attestation_count)`.
+    pub fn stats(&self) -> Result<(u64, u64)> {
+        let manifests = self.conn.query_row("SELECT COUNT(*) FROM manifests", [], |row| row.get(0))?;
+        let attestations = self.conn.query_row("SELECT COUNT(*) FROM attestations", [], |row| row.get(0))?;
+        Ok((manifests, attestations))
+    }
+
+    pub fn save_sync_state(&self, state: &SyncState) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO sync_state
+             (peer, last_sync, started_at, pages_fetched, manifests_received, pending_verification, advertised_total, complete, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                state.peer,
+                state.last_sync,
+                state.started_at,
+                state.pages_fetched,
+                state.manifests_received,
+                state.pending_verification,
+                state.advertised_total,
+                state.complete,
+                state.updated_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_sync_state(&self) -> Result<Vec<SyncState>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT peer, last_sync, started_at, pages_fetched, manifests_received, pending_verification, advertised_total, complete, updated_at
+             FROM sync_state",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(SyncState {
+                peer: row.get(0)?,
+                last_sync: row.get(1)?,
+                started_at: row.get(2)?,
+                pages_fetched: row.get(3)?,
+                manifests_received: row.get(4)?,
+                pending_verification: row.get(5)?,
+                advertised_total: row.get(6)?,
+                complete: row.get(7)?,
+                updated_at: row.get(8)?,
+            })
+        })?;
+        let mut states = Vec::new();
+        for state in rows {
+            states.push(state?);
+        }
+        Ok(states)
+    }
+
+    /// Caches provenance for a manifest already in the catalog; a no-op
+    /// otherwise, so callers store the manifest first.
     pub fn cache_provenance(&self, cid: &str, provenance: &Provenance) -> Result<()> {
         self.conn.execute(
-            "INSERT OR REPLACE INTO provenance (cid, finalized, attestation_count, tx_signature, slot, cached_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![cid, provenance.finalized, provenance.attestation_count, provenance.tx_signature, provenance.slot, chrono::Utc::now().timestamp()],
+            "INSERT OR REPLACE INTO provenance (cid, finalized, attestation_count, tx_signature, slot, anchored_at, cached_at)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7 WHERE EXISTS (SELECT 1 FROM manifests WHERE cid = ?1)",
+            params![
+                cid,
+                provenance.finalized,
+                provenance.attestation_count,
+                provenance.tx_signature,
+                provenance.slot,
+                provenance.anchored_at,
+                chrono::Utc::now().timestamp()
+            ],
         )?;
         Ok(())
     }
 
-    pub fn get_provenance(&self, cid: &str) -> Result<Option<Provenance>> {
-        let mut stmt = self.conn.prepare("SELECT finalized, attestation_count, tx_signature, slot FROM provenance WHERE cid = ?1")?;
+    pub fn get_cached_provenance(&self, cid: &str) -> Result<Option<CachedProvenance>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT finalized, attestation_count, tx_signature, slot, anchored_at, cached_at FROM provenance WHERE cid = ?1",
+        )?;
         let mut rows = stmt.query_map(params![cid], |row| {
-            Ok(Provenance {
-                finalized: row.get(0)?,
-                attestation_count: row.get(1)?,
-                tx_signature: row.get(2)?,
-                slot: row.get(3)?,
+            Ok(CachedProvenance {
+                provenance: Provenance {
+                    finalized: row.get(0)?,
+                    attestation_count: row.get(1)?,
+                    tx_signature: row.get(2)?,
+                    slot: row.get(3)?,
+                    anchored_at: row.get(4)?,
+                },
+                cached_at: row.get::<_, Option<i64>>(5)?.unwrap_or(0),
             })
         })?;
-        if let Some(provenance) = rows.next() {
-            Ok(Some(provenance?))
+        if let Some(cached) = rows.next() {
+            Ok(Some(cached?))
         } else {
             Ok(None)
         }
     }
+
+    /// Drops a cached entry so the next lookup goes to the chain. Returns
+    /// whether there was one.
+    pub fn invalidate_provenance(&self, cid: &str) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM provenance WHERE cid = ?1", params![cid])? > 0)
+    }
+}
+
+impl CatalogStore for Storage {
+    fn merge_manifest(&self, manifest: &Manifest, source: &str, anchored_at: Option<i64>) -> anyhow::Result<MergeOutcome> {
+        Storage::merge_manifest(self, manifest, source, anchored_at)
+    }
+
+    fn get_manifest(&self, cid: &str) -> anyhow::Result<Option<Manifest>> {
+        Storage::get_manifest(self, cid)
+    }
+
+    fn list_manifests(&self) -> anyhow::Result<Vec<Manifest>> {
+        Storage::list_manifests(self)
+    }
+
+    fn query_manifests(&self, query: &ManifestQuery) -> anyhow::Result<Vec<Manifest>> {
+        Storage::query_manifests(self, query)
+    }
+
+    fn query_manifest_summaries(&self, query: &ManifestQuery) -> anyhow::Result<Vec<ManifestSummary>> {
+        Storage::query_manifest_summaries(self, query)
+    }
+
+    fn count_manifests(&self, query: &ManifestQuery) -> anyhow::Result<u64> {
+        Storage::count_manifests(self, query)
+    }
+
+    fn list_conflicts(&self, cid: Option<&str>) -> anyhow::Result<Vec<ManifestConflict>> {
+        Storage::list_conflicts(self, cid)
+    }
+
+    fn record_rejection(&self, rejection: &ManifestRejection) -> anyhow::Result<()> {
+        Storage::record_rejection(self, rejection)
+    }
+
+    fn list_rejections(&self, cid: Option<&str>) -> anyhow::Result<Vec<ManifestRejection>> {
+        Storage::list_rejections(self, cid)
+    }
+
+    fn prune_old(&self, before_timestamp: i64) -> anyhow::Result<usize> {
+        Storage::prune_old(self, before_timestamp)
+    }
+
+    fn apply_tombstone(&self, tombstone: &Tombstone) -> anyhow::Result<()> {
+        Storage::apply_tombstone(self, tombstone)
+    }
+
+    fn get_tombstone(&self, cid: &str) -> anyhow::Result<Option<Tombstone>> {
+        Storage::get_tombstone(self, cid)
+    }
+
+    fn record_retraction(
+        &self,
+        retraction: &Retraction,
+        scope: Option<&RetractedScope>,
+        source: Option<&str>,
+    ) -> anyhow::Result<()> {
+        Storage::record_retraction(self, retraction, scope, source)
+    }
+
+    fn count_pending_retractions(&self, source: &str) -> anyhow::Result<usize> {
+        Storage::count_pending_retractions(self, source)
+    }
+
+    fn get_retractions(&self, cid: &str) -> anyhow::Result<Vec<Retraction>> {
+        Storage::get_retractions(self, cid)
+    }
+
+    fn list_signed_retractions(&self, query: &ManifestQuery) -> anyhow::Result<Vec<Retraction>> {
+        Storage::list_signed_retractions(self, query)
+    }
+
+    fn prune_tombstones(&self, evicted_before: i64, retracted_before: i64) -> anyhow::Result<usize> {
+        Storage::prune_tombstones(self, evicted_before, retracted_before)
+    }
+
+    fn cache_provenance(&self, cid: &str, provenance: &Provenance) -> anyhow::Result<()> {
+        Storage::cache_provenance(self, cid, provenance)
+    }
+
+    fn get_cached_provenance(&self, cid: &str) -> anyhow::Result<Option<CachedProvenance>> {
+        Storage::get_cached_provenance(self, cid)
+    }
+
+    fn invalidate_provenance(&self, cid: &str) -> anyhow::Result<bool> {
+        Storage::invalidate_provenance(self, cid)
+    }
+
+    fn insert_attestation(&self, attestation: &Attestation) -> anyhow::Result<()> {
+        Storage::insert_attestation(self, attestation)
+    }
+
+    fn get_attestations(&self, cid: &str) -> anyhow::Result<Vec<Attestation>> {
+        Storage::get_attestations(self, cid)
+    }
+
+    fn list_namespaces(&self) -> anyhow::Result<Vec<(String, u64)>> {
+        Storage::list_namespaces(self)
+    }
+
+    fn stats(&self) -> anyhow::Result<(u64, u64)> {
+        Storage::stats(self)
+    }
+
+    fn save_sync_state(&self, state: &SyncState) -> anyhow::Result<()> {
+        Storage::save_sync_state(self, state)
+    }
+
+    fn list_sync_state(&self) -> anyhow::Result<Vec<SyncState>> {
+        Storage::list_sync_state(self)
+    }
+}
+
+/// Stores the columns derived from a manifest's document: namespace,
+/// creator, type and tags. Unparsed manifests get the default namespace
+/// and nothing else.
+fn write_metadata(conn: &Connection, manifest: &Manifest) -> Result<()> {
+    let document = manifest.document().ok();
+    conn.execute(
+        "UPDATE manifests SET namespace = ?2, creator = ?3, kind = ?4 WHERE cid = ?1",
+        params![
+            manifest.cid,
+            document.as_ref().map_or(DEFAULT_NAMESPACE, |d| d.namespace()),
+            document.as_ref().map(|d| d.creator.as_str()),
+            document.as_ref().and_then(|d| d.kind.as_deref())
+        ],
+    )?;
+    conn.execute("DELETE FROM manifest_tags WHERE cid = ?1", params![manifest.cid])?;
+    for tag in document.iter().flat_map(|d| &d.tags) {
+        conn.execute(
+            "INSERT OR IGNORE INTO manifest_tags (cid, tag) VALUES (?1, ?2)",
+            params![manifest.cid, tag],
+        )?;
+    }
+    Ok(())
+}
+
+/// Builds the WHERE/ORDER/LIMIT tail for a manifest query. Uses the
+/// `(timestamp, cid)` index for both range and keyset conditions.
+fn manifest_filter(query: &ManifestQuery, paged: bool) -> (String, Vec<Value>) {
+    let mut clauses = Vec::new();
+    let mut values = Vec::new();
+    if let Some(since) = query.since {
+        values.push(Value::Integer(since));
+        clauses.push(format!("timestamp > ?{}", values.len()));
+    }
+    if let Some(until) = query.until {
+        values.push(Value::Integer(until));
+        clauses.push(format!("timestamp <= ?{}", values.len()));
+    }
+    // `{}` stands for the placeholder list
+    for (condition, list) in [
+        ("namespace IN ({})", &query.namespaces),
+        ("creator IN ({})", &query.creators),
+        ("kind IN ({})", &query.types),
+        ("cid IN (SELECT cid FROM manifest_tags WHERE tag IN ({}))", &query.tags),
+    ] {
+        let Some(list) = list else { continue };
+        let mut placeholders = Vec::new();
+        for value in list {
+            values.push(Value::Text(value.clone()));
+            placeholders.push(format!("?{}", values.len()));
+        }
+        // An empty list matches nothing; `IN ()` isn't valid SQLite
+        clauses.push(if placeholders.is_empty() {
+            "0".to_string()
+        } else {
+            condition.replace("{}", &placeholders.join(", "))
+        });
+    }
+    if let Some(after) = &query.after {
+        values.push(Value::Integer(after.timestamp));
+        values.push(Value::Text(after.cid.clone()));
+        clauses.push(format!("(timestamp, cid) > (?{}, ?{})", values.len() - 1, values.len()));
+    }
+    let mut sql = String::new();
+    if !clauses.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&clauses.join(" AND "));
+    }
+    if paged {
+        sql.push_str(" ORDER BY timestamp, cid");
+        if let Some(limit) = query.limit {
+            values.push(Value::Integer(limit as i64));
+            sql.push_str(&format!(" LIMIT ?{}", values.len()));
+        }
+    }
+    (sql, values)
+}
+
+/// Timestamp merge rule shared by every `CatalogStore`: the anchor's time
+/// wins once known, otherwise the earliest timestamp seen.
+pub(crate) fn resolve_timestamp(existing: i64, existing_anchored: bool, incoming: i64, anchored_at: Option<i64>) -> (i64, bool) {
+    match anchored_at {
+        Some(anchored_ts) => (anchored_ts, true),
+        None if existing_anchored => (existing, true),
+        None => (existing.min(incoming), false),
+    }
+}
+
+/// Blob keys of the manifest rows matching `condition`.
+fn blob_keys<P: rusqlite::Params>(conn: &Connection, condition: &str, params: P) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT blob_key FROM manifests WHERE {} AND blob_key IS NOT NULL",
+        condition
+    ))?;
+    let rows = stmt.query_map(params, |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Deletes provenance and attestations of the manifest rows matching
+/// `condition`, which foreign keys require before the rows themselves go.
+fn delete_dependents<P: rusqlite::Params + Copy>(conn: &Connection, condition: &str, params: P) -> Result<()> {
+    conn.execute(
+        &format!("DELETE FROM provenance WHERE cid IN (SELECT cid FROM manifests WHERE {})", condition),
+        params,
+    )?;
+    conn.execute(
+        &format!("DELETE FROM attestations WHERE manifest_cid IN (SELECT cid FROM manifests WHERE {})", condition),
+        params,
+    )?;
+    Ok(())
+}
+
+fn record_conflict(
+    conn: &Connection,
+    cid: &str,
+    source: &str,
+    kind: ConflictKind,
+    existing_timestamp: i64,
+    incoming_timestamp: i64,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO manifest_conflicts (cid, source, kind, existing_timestamp, incoming_timestamp, detected_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![cid, source, kind.as_str(), existing_timestamp, incoming_timestamp, chrono::Utc::now().timestamp()],
+    )?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
 
     #[test]
     fn test_storage() -> Result<()> {
-        let db_path = "test.db";
-        let storage = Storage::new(db_path)?;
+        let storage = Storage::in_memory()?;
         let manifest = Manifest {
             cid: "test_cid".to_string(),
             data: vec![1, 2, 3],
@@ -140,7 +1212,40 @@ mod tests {
         storage.insert_manifest(&manifest)?;
         let retrieved = storage.get_manifest("test_cid")?;
         assert_eq!(retrieved.unwrap().cid, "test_cid");
-        fs::remove_file(db_path)?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_blob_backed_payloads() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nsd-storage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let storage = Storage::new(dir.join("catalog.db"))?;
+        let blobs = BlobStore::new(dir.join("catalog.blobs"))?;
+
+        let data = b"{\"name\": \"model\"}".to_vec();
+        let key = ipfs::compute_cid(&data);
+        storage.insert_manifest(&Manifest { cid: key.clone(), data: data.clone(), timestamp: 10 })?;
+
+        // The row holds only metadata; the payload lives in the blob store
+        let inline: Option<Vec<u8>> = storage.conn.query_row("SELECT data FROM manifests", [], |row| row.get(0))?;
+        assert!(inline.is_none());
+        assert!(blobs.contains(&key));
+        assert_eq!(storage.get_manifest(&key)?.unwrap().data, data);
+        let summaries = storage.query_manifest_summaries(&ManifestQuery::default())?;
+        assert_eq!(summaries[0].size, data.len() as u64);
+
+        // Legacy inline rows are moved out on request
+        storage.conn.execute(
+            "INSERT INTO manifests (cid, data, timestamp) VALUES ('QmLegacy', x'010203', 5)",
+            [],
+        )?;
+        assert_eq!(storage.externalize_blobs()?, 1);
+        assert_eq!(storage.get_manifest("QmLegacy")?.unwrap().data, vec![1, 2, 3]);
+
+        // Evicting the last reference removes the blob
+        storage.prune_old(100)?;
+        assert!(!blobs.contains(&key));
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
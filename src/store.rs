@@ -0,0 +1,793 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::manifest::{Manifest, ManifestDocument, DEFAULT_NAMESPACE};
+use crate::retraction::{RetractedScope, Retraction};
+use crate::storage::{
+    resolve_timestamp, Attestation, CachedProvenance, ConflictKind, ManifestConflict,
+    ManifestCursor, ManifestRejection, ManifestQuery, ManifestSummary, MergeOutcome, Provenance, SyncState, Tombstone,
+    TombstoneReason,
+};
+
+/// Catalog persistence used by sync, index loading and the CLI.
+///
+/// `Storage` is the SQLite implementation; `MemoryStore` keeps everything in
+/// process memory for unit tests and ephemeral gateway nodes. Both must follow
+/// the same merge and tombstone rules.
+pub trait CatalogStore: Send {
+    fn merge_manifest(&self, manifest: &Manifest, source: &str, anchored_at: Option<i64>) -> Result<MergeOutcome>;
+    fn get_manifest(&self, cid: &str) -> Result<Option<Manifest>>;
+    /// Loads every manifest with its data. Prefer `query_manifests` or
+    /// `ManifestPager` for anything that may be large.
+    fn list_manifests(&self) -> Result<Vec<Manifest>>;
+    fn query_manifests(&self, query: &ManifestQuery) -> Result<Vec<Manifest>>;
+    fn query_manifest_summaries(&self, query: &ManifestQuery) -> Result<Vec<ManifestSummary>>;
+    fn count_manifests(&self, query: &ManifestQuery) -> Result<u64>;
+    fn list_conflicts(&self, cid: Option<&str>) -> Result<Vec<ManifestConflict>>;
+    fn record_rejection(&self, rejection: &ManifestRejection) -> Result<()>;
+    fn list_rejections(&self, cid: Option<&str>) -> Result<Vec<ManifestRejection>>;
+    fn prune_old(&self, before_timestamp: i64) -> Result<usize>;
+
+    fn apply_tombstone(&self, tombstone: &Tombstone) -> Result<()>;
+    fn get_tombstone(&self, cid: &str) -> Result<Option<Tombstone>>;
+    fn record_retraction(&self, retraction: &Retraction, scope: Option<&RetractedScope>, source: Option<&str>)
+        -> Result<()>;
+    fn count_pending_retractions(&self, source: &str) -> Result<usize>;
+    fn get_retractions(&self, cid: &str) -> Result<Vec<Retraction>>;
+    fn list_signed_retractions(&self, query: &ManifestQuery) -> Result<Vec<Retraction>>;
+    fn prune_tombstones(&self, evicted_before: i64, retracted_before: i64) -> Result<usize>;
+
+    fn cache_provenance(&self, cid: &str, provenance: &Provenance) -> Result<()>;
+    fn get_cached_provenance(&self, cid: &str) -> Result<Option<CachedProvenance>>;
+    fn invalidate_provenance(&self, cid: &str) -> Result<bool>;
+
+    fn insert_attestation(&self, attestation: &Attestation) -> Result<()>;
+    fn get_attestations(&self, cid: &str) -> Result<Vec<Attestation>>;
+    /// Returns `(manifest_count, attestation_count)`.
+    fn stats(&self) -> Result<(u64, u64)>;
+    /// `(namespace, manifest_count)` for every namespace, by name.
+    fn list_namespaces(&self) -> Result<Vec<(String, u64)>>;
+
+    fn save_sync_state(&self, state: &SyncState) -> Result<()>;
+    fn list_sync_state(&self) -> Result<Vec<SyncState>>;
+
+    /// Cached provenance regardless of age; see `ProvenanceCache` for TTLs.
+    fn get_provenance(&self, cid: &str) -> Result<Option<Provenance>> {
+        Ok(self.get_cached_provenance(cid)?.map(|cached| cached.provenance))
+    }
+
+    fn insert_manifest(&self, manifest: &Manifest) -> Result<MergeOutcome> {
+        self.merge_manifest(manifest, "local", None)
+    }
+}
+
+/// Iterates over manifests matching a query one page at a time, so callers
+/// never hold the whole catalog in memory.
+pub struct ManifestPager<'a> {
+    store: &'a dyn CatalogStore,
+    query: ManifestQuery,
+    page: std::vec::IntoIter<Manifest>,
+    done: bool,
+}
+
+impl<'a> ManifestPager<'a> {
+    /// `query.limit` is used as the page size.
+    pub fn new(store: &'a dyn CatalogStore, query: ManifestQuery) -> Self {
+        Self {
+            store,
+            query,
+            page: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+}
+
+impl Iterator for ManifestPager<'_> {
+    type Item = Result<Manifest>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(manifest) = self.page.next() {
+            return Some(Ok(manifest));
+        }
+        if self.done {
+            return None;
+        }
+        let page = match self.store.query_manifests(&self.query) {
+            Ok(page) => page,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+        if page.len() < self.query.limit.unwrap_or(usize::MAX) {
+            self.done = true;
+        }
+        self.query.after = page.last().map(|m| ManifestCursor {
+            timestamp: m.timestamp,
+            cid: m.cid.clone(),
+        });
+        self.page = page.into_iter();
+        self.page.next().map(Ok)
+    }
+}
+
+/// A retraction record with what `Storage` keeps in its other columns.
+struct StoredRetraction {
+    retraction: Retraction,
+    /// The retracted manifest's scope, once known.
+    scope: Option<RetractedScope>,
+    /// Peer it came from; `None` for this node.
+    source: Option<String>,
+    received_at: i64,
+}
+
+#[derive(Default)]
+struct MemoryCatalog {
+    /// cid -> (manifest, timestamp_anchored)
+    manifests: HashMap<String, (Manifest, bool)>,
+    /// cid -> parsed document, for manifests whose data parses; the
+    /// in-memory counterpart of the SQLite document columns
+    documents: HashMap<String, ManifestDocument>,
+    conflicts: Vec<ManifestConflict>,
+    rejections: HashMap<String, ManifestRejection>,
+    tombstones: HashMap<String, Tombstone>,
+    /// (cid, creator) -> signed retraction
+    retractions: HashMap<(String, String), StoredRetraction>,
+    provenance: HashMap<String, CachedProvenance>,
+    /// (manifest_cid, validator) -> attestation
+    attestations: HashMap<(String, String), Attestation>,
+    sync_state: HashMap<String, SyncState>,
+}
+
+impl MemoryCatalog {
+    /// Drops a manifest with its provenance and attestations, matching the
+    /// foreign keys of the SQLite schema.
+    fn remove_manifest(&mut self, cid: &str) {
+        self.manifests.remove(cid);
+        self.documents.remove(cid);
+        self.provenance.remove(cid);
+        self.attestations.retain(|(manifest_cid, _), _| manifest_cid != cid);
+    }
+}
+
+/// In-process `CatalogStore`. Nothing survives a restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    catalog: Mutex<MemoryCatalog>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn catalog(&self) -> MutexGuard<'_, MemoryCatalog> {
+        // A panic mid-update can't leave a half-written row behind, so keep serving
+        self.catalog.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl MemoryCatalog {
+    fn namespace(&self, cid: &str) -> &str {
+        self.documents.get(cid).map_or(DEFAULT_NAMESPACE, |d| d.namespace())
+    }
+
+    fn record_conflict(&mut self, cid: &str, source: &str, kind: ConflictKind, existing_timestamp: i64, incoming_timestamp: i64) {
+        self.conflicts.push(ManifestConflict {
+            cid: cid.to_string(),
+            source: source.to_string(),
+            kind,
+            existing_timestamp,
+            incoming_timestamp,
+            detected_at: chrono::Utc::now().timestamp(),
+        });
+    }
+}
+
+/// Whether `cid` has a retraction tombstone, i.e. its manifest was seen here.
+fn is_retracted(tombstones: &HashMap<String, Tombstone>, cid: &str) -> bool {
+    tombstones.get(cid).map_or(false, |t| t.reason == TombstoneReason::Retracted)
+}
+
+impl CatalogStore for MemoryStore {
+    fn merge_manifest(&self, manifest: &Manifest, source: &str, anchored_at: Option<i64>) -> Result<MergeOutcome> {
+        let mut catalog = self.catalog();
+        let existing = catalog
+            .manifests
+            .get(&manifest.cid)
+            .map(|(m, anchored)| (m.data == manifest.data, m.timestamp, *anchored));
+        let outcome = match existing {
+            None => {
+                let mut stored = manifest.clone();
+                stored.timestamp = anchored_at.unwrap_or(manifest.timestamp);
+                catalog.manifests.insert(manifest.cid.clone(), (stored, anchored_at.is_some()));
+                catalog.rejections.remove(&manifest.cid);
+                if let Ok(document) = manifest.document() {
+                    catalog.documents.insert(manifest.cid.clone(), document);
+                }
+                info!("Inserted manifest: {}", manifest.cid);
+                MergeOutcome::Inserted
+            }
+            Some((false, timestamp, _)) => {
+                catalog.record_conflict(&manifest.cid, source, ConflictKind::DataMismatch, timestamp, manifest.timestamp);
+                warn!("Rejected manifest {} from {}: data differs from stored copy", manifest.cid, source);
+                MergeOutcome::Conflict
+            }
+            Some((true, timestamp, anchored)) => {
+                if manifest.timestamp != timestamp {
+                    catalog.record_conflict(&manifest.cid, source, ConflictKind::Timestamp, timestamp, manifest.timestamp);
+                }
+                let resolved = resolve_timestamp(timestamp, anchored, manifest.timestamp, anchored_at);
+                if resolved != (timestamp, anchored) {
+                    if let Some((stored, stored_anchored)) = catalog.manifests.get_mut(&manifest.cid) {
+                        stored.timestamp = resolved.0;
+                        *stored_anchored = resolved.1;
+                    }
+                    MergeOutcome::TimestampUpdated
+                } else {
+                    MergeOutcome::Unchanged
+                }
+            }
+        };
+        Ok(outcome)
+    }
+
+    fn get_manifest(&self, cid: &str) -> Result<Option<Manifest>> {
+        Ok(self.catalog().manifests.get(cid).map(|(m, _)| m.clone()))
+    }
+
+    fn list_manifests(&self) -> Result<Vec<Manifest>> {
+        Ok(self.catalog().manifests.values().map(|(m, _)| m.clone()).collect())
+    }
+
+    fn query_manifests(&self, query: &ManifestQuery) -> Result<Vec<Manifest>> {
+        let catalog = self.catalog();
+        let mut matches: Vec<&Manifest> = catalog
+            .manifests
+            .values()
+            .map(|(m, _)| m)
+            .filter(|m| query.matches(m.timestamp, &m.cid) && query.matches_document(catalog.documents.get(&m.cid)))
+            .collect();
+        matches.sort_by(|a, b| (a.timestamp, &a.cid).cmp(&(b.timestamp, &b.cid)));
+        Ok(matches
+            .into_iter()
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
+    }
+
+    fn query_manifest_summaries(&self, query: &ManifestQuery) -> Result<Vec<ManifestSummary>> {
+        let manifests = self.query_manifests(query)?;
+        let catalog = self.catalog();
+        Ok(manifests
+            .into_iter()
+            .map(|m| ManifestSummary {
+                size: m.data.len() as u64,
+                namespace: catalog.namespace(&m.cid).to_string(),
+                cid: m.cid,
+                timestamp: m.timestamp,
+            })
+            .collect())
+    }
+
+    fn count_manifests(&self, query: &ManifestQuery) -> Result<u64> {
+        let catalog = self.catalog();
+        Ok(catalog
+            .manifests
+            .values()
+            .filter(|(m, _)| query.matches(m.timestamp, &m.cid) && query.matches_document(catalog.documents.get(&m.cid)))
+            .count() as u64)
+    }
+
+    fn list_conflicts(&self, cid: Option<&str>) -> Result<Vec<ManifestConflict>> {
+        Ok(self
+            .catalog()
+            .conflicts
+            .iter()
+            .filter(|c| cid.map_or(true, |cid| c.cid == cid))
+            .cloned()
+            .collect())
+    }
+
+    fn record_rejection(&self, rejection: &ManifestRejection) -> Result<()> {
+        self.catalog().rejections.insert(rejection.cid.clone(), rejection.clone());
+        Ok(())
+    }
+
+    fn list_rejections(&self, cid: Option<&str>) -> Result<Vec<ManifestRejection>> {
+        let mut rejections: Vec<ManifestRejection> = self
+            .catalog()
+            .rejections
+            .values()
+            .filter(|r| cid.map_or(true, |cid| r.cid == cid))
+            .cloned()
+            .collect();
+        rejections.sort_by_key(|r| r.rejected_at);
+        Ok(rejections)
+    }
+
+    fn prune_old(&self, before_timestamp: i64) -> Result<usize> {
+        let now = chrono::Utc::now().timestamp();
+        let mut catalog = self.catalog();
+        let old: Vec<String> = catalog
+            .manifests
+            .values()
+            .filter(|(m, _)| m.timestamp < before_timestamp)
+            .map(|(m, _)| m.cid.clone())
+            .collect();
+        for cid in &old {
+            catalog.remove_manifest(cid);
+            catalog.tombstones.entry(cid.clone()).or_insert_with(|| Tombstone {
+                cid: cid.clone(),
+                deleted_at: now,
+                reason: TombstoneReason::Evicted,
+            });
+        }
+        warn!("Pruned {} old manifests", old.len());
+        Ok(old.len())
+    }
+
+    fn apply_tombstone(&self, tombstone: &Tombstone) -> Result<()> {
+        let mut catalog = self.catalog();
+        catalog.remove_manifest(&tombstone.cid);
+        let retracted = catalog
+            .tombstones
+            .get(&tombstone.cid)
+            .map_or(false, |t| t.reason == TombstoneReason::Retracted);
+        if !retracted {
+            catalog.tombstones.insert(tombstone.cid.clone(), tombstone.clone());
+        }
+        Ok(())
+    }
+
+    fn get_tombstone(&self, cid: &str) -> Result<Option<Tombstone>> {
+        Ok(self.catalog().tombstones.get(cid).cloned())
+    }
+
+    fn record_retraction(&self, retraction: &Retraction, scope: Option<&RetractedScope>, source: Option<&str>) -> Result<()> {
+        let mut catalog = self.catalog();
+        let stored = catalog
+            .retractions
+            .entry((retraction.cid.clone(), retraction.creator.clone()))
+            .or_insert_with(|| StoredRetraction {
+                retraction: retraction.clone(),
+                scope: None,
+                source: source.map(str::to_string),
+                received_at: chrono::Utc::now().timestamp(),
+            });
+        if stored.scope.is_none() {
+            stored.scope = scope.cloned();
+        }
+        Ok(())
+    }
+
+    fn count_pending_retractions(&self, source: &str) -> Result<usize> {
+        let catalog = self.catalog();
+        Ok(catalog
+            .retractions
+            .values()
+            .filter(|r| r.source.as_deref() == Some(source) && !is_retracted(&catalog.tombstones, &r.retraction.cid))
+            .count())
+    }
+
+    fn get_retractions(&self, cid: &str) -> Result<Vec<Retraction>> {
+        let mut retractions: Vec<Retraction> =
+            self.catalog().retractions.values().map(|r| &r.retraction).filter(|r| r.cid == cid).cloned().collect();
+        retractions.sort_by(|a, b| (a.retracted_at, &a.cid).cmp(&(b.retracted_at, &b.cid)));
+        Ok(retractions)
+    }
+
+    fn list_signed_retractions(&self, query: &ManifestQuery) -> Result<Vec<Retraction>> {
+        let catalog = self.catalog();
+        let since = query.since.unwrap_or(0);
+        let mut retractions: Vec<Retraction> = catalog
+            .retractions
+            .values()
+            .filter(|r| {
+                catalog
+                    .tombstones
+                    .get(&r.retraction.cid)
+                    .map_or(false, |t| t.reason == TombstoneReason::Retracted && t.deleted_at > since)
+                    && query.matches_retraction(&r.retraction.creator, r.scope.as_ref())
+            })
+            .map(|r| r.retraction.clone())
+            .collect();
+        retractions.sort_by(|a, b| (a.retracted_at, &a.cid).cmp(&(b.retracted_at, &b.cid)));
+        Ok(retractions)
+    }
+
+    fn prune_tombstones(&self, evicted_before: i64, retracted_before: i64) -> Result<usize> {
+        let mut catalog = self.catalog();
+        let before = catalog.tombstones.len();
+        catalog.tombstones.retain(|_, t| match t.reason {
+            TombstoneReason::Evicted => t.deleted_at >= evicted_before,
+            TombstoneReason::Retracted => t.deleted_at >= retracted_before,
+        });
+        let pruned = before - catalog.tombstones.len();
+        let MemoryCatalog { retractions, tombstones, .. } = &mut *catalog;
+        retractions.retain(|_, r| r.received_at >= retracted_before || is_retracted(tombstones, &r.retraction.cid));
+        Ok(pruned)
+    }
+
+    fn cache_provenance(&self, cid: &str, provenance: &Provenance) -> Result<()> {
+        let mut catalog = self.catalog();
+        if catalog.manifests.contains_key(cid) {
+            let cached = CachedProvenance {
+                provenance: provenance.clone(),
+                cached_at: chrono::Utc::now().timestamp(),
+            };
+            catalog.provenance.insert(cid.to_string(), cached);
+        }
+        Ok(())
+    }
+
+    fn get_cached_provenance(&self, cid: &str) -> Result<Option<CachedProvenance>> {
+        Ok(self.catalog().provenance.get(cid).cloned())
+    }
+
+    fn invalidate_provenance(&self, cid: &str) -> Result<bool> {
+        Ok(self.catalog().provenance.remove(cid).is_some())
+    }
+
+    fn insert_attestation(&self, attestation: &Attestation) -> Result<()> {
+        let key = (attestation.manifest_cid.clone(), attestation.validator.clone());
+        let mut catalog = self.catalog();
+        if !catalog.manifests.contains_key(&attestation.manifest_cid) {
+            return Ok(());
+        }
+        let newer = catalog
+            .attestations
+            .get(&key)
+            .map_or(true, |existing| attestation.timestamp > existing.timestamp);
+        if newer {
+            catalog.attestations.insert(key, attestation.clone());
+        }
+        Ok(())
+    }
+
+    fn get_attestations(&self, cid: &str) -> Result<Vec<Attestation>> {
+        Ok(self
+            .catalog()
+            .attestations
+            .values()
+            .filter(|a| a.manifest_cid == cid)
+            .cloned()
+            .collect())
+    }
+
+    fn list_namespaces(&self) -> Result<Vec<(String, u64)>> {
+        let mut counts = std::collections::BTreeMap::new();
+        let catalog = self.catalog();
+        for cid in catalog.manifests.keys() {
+            *counts.entry(catalog.namespace(cid).to_string()).or_insert(0) += 1;
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    fn stats(&self) -> Result<(u64, u64)> {
+        let catalog = self.catalog();
+        Ok((catalog.manifests.len() as u64, catalog.attestations.len() as u64))
+    }
+
+    fn save_sync_state(&self, state: &SyncState) -> Result<()> {
+        self.catalog().sync_state.insert(state.peer.clone(), state.clone());
+        Ok(())
+    }
+
+    fn list_sync_state(&self) -> Result<Vec<SyncState>> {
+        Ok(self.catalog().sync_state.values().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    /// Runs `check` against every backend so they can't drift apart.
+    fn for_each_backend(check: fn(&dyn CatalogStore) -> Result<()>) -> Result<()> {
+        check(&MemoryStore::new())?;
+        check(&Storage::in_memory()?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_tombstones() -> Result<()> {
+        for_each_backend(|store| {
+            for (cid, timestamp) in [("old_cid", 100), ("new_cid", 200)] {
+                store.insert_manifest(&Manifest {
+                    cid: cid.to_string(),
+                    data: vec![1],
+                    timestamp,
+                })?;
+            }
+            assert_eq!(store.prune_old(150)?, 1);
+            assert_eq!(store.get_tombstone("old_cid")?.unwrap().reason, TombstoneReason::Evicted);
+
+            store.apply_tombstone(&Tombstone {
+                cid: "new_cid".to_string(),
+                deleted_at: 300,
+                reason: TombstoneReason::Retracted,
+            })?;
+            assert!(store.get_manifest("new_cid")?.is_none());
+
+            // A later eviction must not downgrade the retraction.
+            store.apply_tombstone(&Tombstone {
+                cid: "new_cid".to_string(),
+                deleted_at: 0,
+                reason: TombstoneReason::Evicted,
+            })?;
+            assert_eq!(store.get_tombstone("new_cid")?.unwrap().reason, TombstoneReason::Retracted);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_retraction_filters() -> Result<()> {
+        for_each_backend(|store| {
+            let retraction = |cid: &str| Retraction {
+                cid: cid.to_string(),
+                creator: "alice".to_string(),
+                reason: "leaked".to_string(),
+                retracted_at: 1,
+                signature: "sig".to_string(),
+            };
+            let scope = RetractedScope {
+                namespace: "lab".to_string(),
+                kind: Some("model".to_string()),
+                tags: vec!["ai".to_string()],
+            };
+            store.record_retraction(&retraction("scoped"), Some(&scope), None)?;
+            store.record_retraction(&retraction("unscoped"), None, None)?;
+            for cid in ["scoped", "unscoped"] {
+                store.apply_tombstone(&retraction(cid).tombstone(10))?;
+            }
+            let cids = |query: ManifestQuery| -> Result<Vec<String>> {
+                Ok(store.list_signed_retractions(&query)?.into_iter().map(|r| r.cid).collect())
+            };
+            assert_eq!(cids(ManifestQuery::default())?, ["scoped", "unscoped"]);
+            assert!(cids(ManifestQuery { since: Some(10), ..Default::default() })?.is_empty());
+            assert_eq!(cids(ManifestQuery { creators: Some(vec!["alice".to_string()]), ..Default::default() })?.len(), 2);
+            // Without a scope, a record can't be shown to match a namespace
+            assert_eq!(cids(ManifestQuery { namespaces: Some(vec!["lab".to_string()]), ..Default::default() })?, ["scoped"]);
+            assert!(cids(ManifestQuery { namespaces: Some(vec!["other".to_string()]), ..Default::default() })?.is_empty());
+
+            // The manifest turning up later fills the scope in
+            store.record_retraction(&retraction("unscoped"), Some(&scope), None)?;
+            assert_eq!(cids(ManifestQuery { tags: Some(vec!["ai".to_string()]), ..Default::default() })?.len(), 2);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_pending_retractions_expire() -> Result<()> {
+        for_each_backend(|store| {
+            let retraction = |cid: &str| Retraction {
+                cid: cid.to_string(),
+                creator: "alice".to_string(),
+                reason: "leaked".to_string(),
+                retracted_at: 1,
+                signature: "sig".to_string(),
+            };
+            store.record_retraction(&retraction("applied"), None, Some("peer"))?;
+            store.record_retraction(&retraction("pending"), None, Some("peer"))?;
+            store.record_retraction(&retraction("local"), None, None)?;
+            let now = chrono::Utc::now().timestamp();
+            store.apply_tombstone(&retraction("applied").tombstone(now))?;
+            assert_eq!(store.count_pending_retractions("peer")?, 1);
+            assert_eq!(store.count_pending_retractions("other")?, 0);
+
+            // Records still backed by a kept tombstone stay; the rest go with the window
+            store.prune_tombstones(0, now + 1)?;
+            assert_eq!(store.get_retractions("applied")?.len(), 0);
+            store.record_retraction(&retraction("applied"), None, Some("peer"))?;
+            store.apply_tombstone(&retraction("applied").tombstone(now + 10))?;
+            store.prune_tombstones(0, now + 1)?;
+            assert_eq!(store.get_retractions("applied")?.len(), 1);
+            assert!(store.get_retractions("pending")?.is_empty());
+            assert!(store.get_retractions("local")?.is_empty());
+            assert_eq!(store.count_pending_retractions("peer")?, 0);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_attestations() -> Result<()> {
+        for_each_backend(|store| {
+            store.insert_manifest(&Manifest {
+                cid: "test_cid".to_string(),
+                data: vec![1],
+                timestamp: 1,
+            })?;
+            let mut attestation = Attestation {
+                manifest_cid: "test_cid".to_string(),
+                validator: "validator1".to_string(),
+                confidence: 0.9,
+                timestamp: 10,
+                signature: "sig".to_string(),
+            };
+            store.insert_attestation(&attestation)?;
+            // Older attestation from the same validator is ignored
+            attestation.confidence = 0.1;
+            attestation.timestamp = 5;
+            store.insert_attestation(&attestation)?;
+            let stored = store.get_attestations("test_cid")?;
+            assert_eq!(stored.len(), 1);
+            assert_eq!(stored[0].confidence, 0.9);
+            assert_eq!(store.stats()?, (1, 1));
+
+            // Dependents go with the manifest, and can't be added without one
+            store.apply_tombstone(&Tombstone {
+                cid: "test_cid".to_string(),
+                deleted_at: 15,
+                reason: TombstoneReason::Evicted,
+            })?;
+            assert_eq!(store.stats()?, (0, 0));
+            attestation.timestamp = 20;
+            store.insert_attestation(&attestation)?;
+            assert!(store.get_attestations("test_cid")?.is_empty());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_merge_manifest() -> Result<()> {
+        for_each_backend(|store| {
+            let manifest = Manifest {
+                cid: "test_cid".to_string(),
+                data: vec![1, 2, 3],
+                timestamp: 200,
+            };
+            assert_eq!(store.merge_manifest(&manifest, "peer_a", None)?, MergeOutcome::Inserted);
+
+            // Earliest unanchored timestamp wins
+            let earlier = Manifest { timestamp: 100, ..manifest.clone() };
+            assert_eq!(store.merge_manifest(&earlier, "peer_b", None)?, MergeOutcome::TimestampUpdated);
+            assert_eq!(store.get_manifest("test_cid")?.unwrap().timestamp, 100);
+
+            // Anchor time overrides, and sticks against later unanchored copies
+            assert_eq!(store.merge_manifest(&manifest, "peer_c", Some(150))?, MergeOutcome::TimestampUpdated);
+            let earliest = Manifest { timestamp: 50, ..manifest.clone() };
+            assert_eq!(store.merge_manifest(&earliest, "peer_d", None)?, MergeOutcome::Unchanged);
+            assert_eq!(store.get_manifest("test_cid")?.unwrap().timestamp, 150);
+
+            // Different data under the same CID is rejected
+            let forged = Manifest { data: vec![9], ..manifest.clone() };
+            assert_eq!(store.merge_manifest(&forged, "peer_e", None)?, MergeOutcome::Conflict);
+            assert_eq!(store.get_manifest("test_cid")?.unwrap().data, vec![1, 2, 3]);
+
+            let conflicts = store.list_conflicts(Some("test_cid"))?;
+            assert_eq!(conflicts.len(), 4);
+            assert_eq!(conflicts.last().unwrap().kind, ConflictKind::DataMismatch);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_rejections() -> Result<()> {
+        for_each_backend(|store| {
+            let rejection = ManifestRejection {
+                cid: "test_cid".to_string(),
+                source: "peer_a".to_string(),
+                reason: "bad creator signature".to_string(),
+                rejected_at: 10,
+            };
+            store.record_rejection(&rejection)?;
+            store.record_rejection(&ManifestRejection { reason: "anchor verification failed".to_string(), ..rejection })?;
+            let rejections = store.list_rejections(None)?;
+            assert_eq!(rejections.len(), 1);
+            assert_eq!(rejections[0].reason, "anchor verification failed");
+
+            // A copy that does get stored clears the rejection
+            store.insert_manifest(&Manifest { cid: "test_cid".to_string(), data: vec![1], timestamp: 1 })?;
+            assert!(store.list_rejections(Some("test_cid"))?.is_empty());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_paged_queries() -> Result<()> {
+        for_each_backend(|store| {
+            for i in 0..10 {
+                store.insert_manifest(&Manifest {
+                    cid: format!("cid_{}", i),
+                    data: vec![0; i],
+                    timestamp: 100 + (i as i64 / 2),
+                })?;
+            }
+            let range = ManifestQuery {
+                since: Some(100),
+                until: Some(103),
+                ..Default::default()
+            };
+            assert_eq!(store.count_manifests(&range)?, 6);
+
+            // Pages of 4 visit every row once, in (timestamp, cid) order
+            let paged: Vec<String> = ManifestPager::new(store, ManifestQuery { limit: Some(4), ..Default::default() })
+                .map(|m| m.map(|m| m.cid))
+                .collect::<Result<_>>()?;
+            let expected: Vec<String> = (0..10).map(|i| format!("cid_{}", i)).collect();
+            assert_eq!(paged, expected);
+
+            let summaries = store.query_manifest_summaries(&ManifestQuery {
+                after: Some(ManifestCursor { timestamp: 104, cid: "cid_8".to_string() }),
+                ..Default::default()
+            })?;
+            assert_eq!(summaries.len(), 1);
+            assert_eq!((summaries[0].cid.as_str(), summaries[0].size), ("cid_9", 9));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_namespaces() -> Result<()> {
+        for_each_backend(|store| {
+            for (cid, namespace) in [("cid_a", Some("vision")), ("cid_b", Some("nlp")), ("cid_c", None)] {
+                let mut document = serde_json::json!({
+                    "schema_version": 1,
+                    "creator": "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS",
+                    "node_id": "node1",
+                    "created_at": 1
+                });
+                if let Some(namespace) = namespace {
+                    document["namespace"] = namespace.into();
+                }
+                store.insert_manifest(&Manifest { cid: cid.to_string(), data: document.to_string().into_bytes(), timestamp: 1 })?;
+            }
+            // Not a document at all
+            store.insert_manifest(&Manifest { cid: "cid_d".to_string(), data: vec![1], timestamp: 1 })?;
+
+            let query = ManifestQuery { namespaces: Some(vec!["vision".to_string()]), ..Default::default() };
+            let cids: Vec<String> = store.query_manifests(&query)?.into_iter().map(|m| m.cid).collect();
+            assert_eq!(cids, vec!["cid_a".to_string()]);
+            assert_eq!(store.count_manifests(&ManifestQuery { namespaces: Some(Vec::new()), ..Default::default() })?, 0);
+            assert_eq!(
+                store.list_namespaces()?,
+                vec![
+                    ("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS".to_string(), 1),
+                    (DEFAULT_NAMESPACE.to_string(), 1),
+                    ("nlp".to_string(), 1),
+                    ("vision".to_string(), 1),
+                ]
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_document_filters() -> Result<()> {
+        for_each_backend(|store| {
+            for (cid, kind, tags) in [("cid_a", "model", vec!["llm", "vision"]), ("cid_b", "dataset", vec!["vision"])] {
+                let document = serde_json::json!({
+                    "schema_version": 1,
+                    "type": kind,
+                    "creator": "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS",
+                    "node_id": "node1",
+                    "tags": tags,
+                    "created_at": 1
+                });
+                store.insert_manifest(&Manifest { cid: cid.to_string(), data: document.to_string().into_bytes(), timestamp: 1 })?;
+            }
+            store.insert_manifest(&Manifest { cid: "cid_c".to_string(), data: vec![1], timestamp: 1 })?;
+
+            let cids = |query: ManifestQuery| -> Result<Vec<String>> {
+                Ok(store.query_manifests(&query)?.into_iter().map(|m| m.cid).collect())
+            };
+            let list = |values: &[&str]| Some(values.iter().map(|v| v.to_string()).collect::<Vec<_>>());
+            assert_eq!(cids(ManifestQuery { tags: list(&["vision"]), ..Default::default() })?, vec!["cid_a", "cid_b"]);
+            assert_eq!(cids(ManifestQuery { types: list(&["model"]), ..Default::default() })?, vec!["cid_a"]);
+            assert_eq!(
+                cids(ManifestQuery {
+                    creators: list(&["Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS"]),
+                    tags: list(&["llm", "nlp"]),
+                    ..Default::default()
+                })?,
+                vec!["cid_a"]
+            );
+            assert_eq!(store.count_manifests(&ManifestQuery { creators: list(&["someone"]), ..Default::default() })?, 0);
+            Ok(())
+        })
+    }
+}
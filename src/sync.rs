@@ -1,96 +1,734 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
 use anyhow::Result;
-use tracing::{info, warn, error};
-use crate::network::SyncMessage;
+use tracing::{debug, info, warn, error};
 
-use crate::storage::{Storage, Manifest};
+use crate::async_storage::AsyncStorage;
+use crate::config::{NamespaceConfig, ProvenanceCacheConfig};
+use crate::provenance::{CacheStats, ProvenanceCache};
+use crate::manifest::Manifest;
+use crate::schema::SchemaRegistry;
+use crate::storage::{CatalogFilter, ManifestCursor, ManifestQuery, ManifestRejection, MergeOutcome, SyncState};
 use crate::network::{Peer, SyncMessage, Network};
-use crate::anchor::{Anchor, ManifestProvenance};
+use crate::anchor::{self, Anchor, ManifestProvenance};
+use crate::index::Index;
+use crate::ingest::{self, Admission};
+use crate::ipfs::IpfsCache;
+use crate::retraction::{self, Retraction, RetractionOutcome};
+
+/// Manifests per `CatalogChunk`.
+const CATALOG_PAGE_SIZE: usize = 100;
+
+/// How often `run_sync` asks every connected peer for catalog updates.
+const RESYNC_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Progress notifications published by the `SyncEngine`.
+#[derive(Debug, Clone)]
+pub enum SyncEvent {
+    Started { peer: SocketAddr },
+    PageReceived { peer: SocketAddr, state: SyncState },
+    ManifestStored { peer: SocketAddr, cid: String },
+    ManifestRejected { peer: SocketAddr, cid: String, reason: String },
+    ManifestRetracted { peer: SocketAddr, cid: String },
+    Completed { peer: SocketAddr, state: SyncState },
+    Failed { peer: SocketAddr, error: String },
+}
+
+/// Aggregate view over every peer's `SyncState`.
+#[derive(Debug, Clone, Default)]
+pub struct SyncProgress {
+    pub peers: Vec<SyncState>,
+}
+
+impl SyncProgress {
+    pub fn from_states(peers: Vec<SyncState>) -> Self {
+        Self { peers }
+    }
+
+    /// Overall completion across peers that advertised a catalog size.
+    pub fn percent(&self) -> Option<f64> {
+        let (received, total) = self
+            .peers
+            .iter()
+            .filter_map(|p| p.advertised_total.map(|t| (p.manifests_received.min(t), t)))
+            .fold((0u64, 0u64), |(r, t), (pr, pt)| (r + pr, t + pt));
+        if total == 0 {
+            if !self.peers.is_empty() && self.peers.iter().all(|p| p.complete) {
+                return Some(100.0);
+            }
+            return None;
+        }
+        Some(received as f64 * 100.0 / total as f64)
+    }
+
+    pub fn pending_verification(&self) -> u64 {
+        self.peers.iter().map(|p| p.pending_verification).sum()
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.peers.iter().filter_map(|p| p.remaining()).sum()
+    }
+
+    /// Peers whose last catalog sync ran to completion.
+    pub fn synced(&self) -> usize {
+        self.peers.iter().filter(|p| p.complete).count()
+    }
+}
 
 pub struct SyncEngine {
-    storage: Arc<Storage>,
+    storage: AsyncStorage,
     network: Arc<Network>,
     anchor: Arc<Anchor>,
-    last_sync: Arc<RwLock<HashMap<std::net::SocketAddr, i64>>>,
+    provenance: ProvenanceCache,
+    schemas: Arc<SchemaRegistry>,
+    replicate: CatalogFilter,
+    namespaces: NamespaceConfig,
+    /// Unpins retracted manifests and their artifacts when set.
+    pins: Option<Arc<IpfsCache>>,
+    progress: Arc<RwLock<HashMap<SocketAddr, SyncState>>>,
+    /// Peers that have advertised each CID, consulted first by `fetch_manifest`.
+    holders: Arc<RwLock<HashMap<String, HashSet<SocketAddr>>>>,
+    events: broadcast::Sender<SyncEvent>,
 }
 
 impl SyncEngine {
-    pub fn new(storage: Arc<Storage>, network: Arc<Network>, anchor: Arc<Anchor>) -> Self {
+    pub async fn new(
+        storage: AsyncStorage,
+        network: Arc<Network>,
+        anchor: Arc<Anchor>,
+        provenance_cache: ProvenanceCacheConfig,
+        schemas: Arc<SchemaRegistry>,
+        replicate: CatalogFilter,
+        namespaces: NamespaceConfig,
+    ) -> Self {
+        // Resume from the last persisted state so restarts don't refetch everything
+        let mut progress = HashMap::new();
+        match storage.list_sync_state().await {
+            Ok(states) => {
+                for state in states {
+                    if let Ok(addr) = state.peer.parse() {
+                        progress.insert(addr, state);
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to load sync state: {:?}", e),
+        }
+        let (events, _) = broadcast::channel(256);
+        let provenance = ProvenanceCache::new(anchor.clone(), storage.clone(), provenance_cache);
         Self {
             storage,
             network,
             anchor,
-            last_sync: Arc::new(RwLock::new(HashMap::new())),
+            provenance,
+            schemas,
+            replicate,
+            namespaces,
+            pins: None,
+            progress: Arc::new(RwLock::new(progress)),
+            holders: Arc::new(RwLock::new(HashMap::new())),
+            events,
+        }
+    }
+
+    /// Unpin manifests from `ipfs` when their creator retracts them.
+    pub fn with_pins(mut self, ipfs: Arc<IpfsCache>) -> Self {
+        self.pins = Some(ipfs);
+        self
+    }
+
+    /// Subscribes to sync progress events.
+    pub fn subscribe(&self) -> broadcast::Receiver<SyncEvent> {
+        self.events.subscribe()
+    }
+
+    /// Hit/miss counters of the provenance cache since startup.
+    pub fn provenance_stats(&self) -> CacheStats {
+        self.provenance.stats()
+    }
+
+    pub async fn progress(&self) -> SyncProgress {
+        SyncProgress::from_states(self.progress.read().await.values().cloned().collect())
+    }
+
+    /// Answers peers' sync messages, and handles the replies to this node's,
+    /// one at a time. Runs until the process exits; start it next to
+    /// `Network::start`.
+    pub async fn serve(&self) -> Result<()> {
+        let mut requests = self.network.incoming().await;
+        while let Some(request) = requests.recv().await {
+            let reply = match self.handle_sync_message(request.addr, request.message).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    warn!("Failed to handle sync message from {}: {:?}", request.addr, e);
+                    None
+                }
+            };
+            // The peer may have given up on the stream already
+            let _ = request.reply.send(reply);
+        }
+        Ok(())
+    }
+
+    /// Syncs with each peer as its handshake completes, and with every
+    /// connected peer once per `RESYNC_INTERVAL`. Runs until the network
+    /// shuts down; start it next to `serve`.
+    pub async fn run_sync(&self) -> Result<()> {
+        // Subscribe before the first round so no handshake slips between them
+        let mut connected = self.network.subscribe_connections();
+        // The first tick fires at once and covers peers already connected
+        let mut resync = tokio::time::interval(RESYNC_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = resync.tick() => self.start_sync().await?,
+                peer = connected.recv() => match peer {
+                    Ok(addr) => self.try_sync_with_peer(addr).await,
+                    Err(broadcast::error::RecvError::Lagged(_)) => self.start_sync().await?,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+            }
         }
     }
 
+    /// Requests updates from every connected peer that isn't mid-sync.
     pub async fn start_sync(&self) -> Result<()> {
-        let peers = self.network.peers.read().await.clone();
-        for (addr, _peer) in peers {
-            if let Err(e) = self.sync_with_peer(addr).await {
-                warn!("Failed to sync with peer {}: {:?}", addr, e);
+        for addr in self.network.peer_addrs().await {
+            if self.is_syncing(addr).await {
+                debug!("Sync with {} still in progress", addr);
+                continue;
             }
+            self.try_sync_with_peer(addr).await;
         }
         Ok(())
     }
 
-    async fn sync_with_peer(&self, addr: std::net::SocketAddr) -> Result<()> {
-        let last_sync = *self.last_sync.read().await.get(&addr).unwrap_or(&0);
-        let request = SyncMessage::RequestCatalog { since: Some(last_sync) };
+    /// A sync counts as in progress until it completes or goes quiet for a
+    /// whole `RESYNC_INTERVAL`.
+    async fn is_syncing(&self, addr: SocketAddr) -> bool {
+        let now = chrono::Utc::now().timestamp();
+        matches!(
+            self.progress.read().await.get(&addr),
+            Some(state) if state.started_at > 0
+                && !state.complete
+                && now - state.updated_at < RESYNC_INTERVAL.as_secs() as i64
+        )
+    }
+
+    async fn try_sync_with_peer(&self, addr: SocketAddr) {
+        if let Err(e) = self.sync_with_peer(addr).await {
+            warn!("Failed to sync with peer {}: {:?}", addr, e);
+            self.emit(SyncEvent::Failed { peer: addr, error: e.to_string() });
+        }
+    }
+
+    async fn sync_with_peer(&self, addr: SocketAddr) -> Result<()> {
+        let last_sync = self.progress.read().await.get(&addr).map(|s| s.last_sync).unwrap_or(0);
+        let request = SyncMessage::RequestCatalog {
+            since: Some(last_sync),
+            after: None,
+            filter: self.replicate.clone(),
+        };
         self.network.send_sync_message(addr, &request).await?;
-        // TODO: Handle response - for now assume it works
-        self.last_sync.write().await.insert(addr, chrono::Utc::now().timestamp());
+        let now = chrono::Utc::now().timestamp();
+        let state = self
+            .update_progress(addr, |state| {
+                *state = SyncState {
+                    peer: addr.to_string(),
+                    last_sync,
+                    started_at: now,
+                    ..Default::default()
+                };
+            })
+            .await;
+        self.save_progress(&state).await;
+        self.emit(SyncEvent::Started { peer: addr });
         info!("Requested sync from peer: {}", addr);
         Ok(())
     }
 
-    pub async fn handle_sync_message(&self, addr: std::net::SocketAddr, message: SyncMessage) -> Result<Option<SyncMessage>> {
+    /// Applies `f` to the peer's in-memory progress and returns a snapshot.
+    /// Nothing is written; see `save_progress`.
+    async fn update_progress<F: FnOnce(&mut SyncState)>(&self, addr: SocketAddr, f: F) -> SyncState {
+        let mut progress = self.progress.write().await;
+        let state = progress.entry(addr).or_insert_with(|| SyncState {
+            peer: addr.to_string(),
+            ..Default::default()
+        });
+        f(state);
+        state.updated_at = chrono::Utc::now().timestamp();
+        state.clone()
+    }
+
+    /// Persists a progress snapshot. Called when a sync starts, after each
+    /// page and on completion, so the catalog sees one write per page
+    /// rather than one per manifest.
+    async fn save_progress(&self, state: &SyncState) {
+        if let Err(e) = self.storage.save_sync_state(state).await {
+            warn!("Failed to persist sync state for {}: {:?}", state.peer, e);
+        }
+    }
+
+    /// Narrows a peer's requested namespaces to the ones this node serves.
+    fn served_namespaces(&self, requested: Option<Vec<String>>) -> Option<Vec<String>> {
+        match requested {
+            Some(requested) => Some(requested.into_iter().filter(|n| self.namespaces.serves(n)).collect()),
+            None if self.namespaces.serve.is_empty() => None,
+            None => Some(self.namespaces.serve.clone()),
+        }
+    }
+
+    fn emit(&self, event: SyncEvent) {
+        // No subscribers is fine
+        let _ = self.events.send(event);
+    }
+
+    /// Returns the manifest for `cid`, asking peers for it if it isn't stored
+    /// locally. Peers known to hold the CID are tried first; the payload must
+    /// hash to `cid` and pass anchor verification before it is stored.
+    pub async fn fetch_manifest(&self, cid: &str) -> Result<Option<Manifest>> {
+        if let Some(manifest) = self.storage.get_manifest(cid).await? {
+            return Ok(Some(manifest));
+        }
+        let holders = self.holders.read().await.get(cid).cloned().unwrap_or_default();
+        let mut candidates: Vec<SocketAddr> = holders.iter().copied().collect();
+        candidates.extend(self.network.peer_addrs().await.into_iter().filter(|addr| !holders.contains(addr)));
+
+        for addr in candidates {
+            let request = SyncMessage::RequestManifest { cid: cid.to_string() };
+            match self.network.request_sync_message(addr, &request).await {
+                Ok(SyncMessage::ManifestData { cid: got, data }) if got == cid => {
+                    if self.store_manifest_data(addr, cid, data).await? {
+                        return Ok(self.storage.get_manifest(cid).await?);
+                    }
+                }
+                Ok(SyncMessage::ManifestNotFound { .. }) => {
+                    self.forget_holder(cid, addr).await;
+                }
+                Ok(other) => warn!("Unexpected reply to RequestManifest from {}: {:?}", addr, other),
+                Err(e) => warn!("Failed to fetch {} from {}: {:?}", cid, addr, e),
+            }
+        }
+        info!("No peer could supply manifest {}", cid);
+        Ok(None)
+    }
+
+    /// Pushes a locally published manifest to every connected peer. Returns
+    /// how many peers it was sent to.
+    pub async fn announce(&self, manifest: &Manifest) -> usize {
+        let message = SyncMessage::AnnounceManifest { manifest: manifest.clone() };
+        let mut sent = 0;
+        for addr in self.network.peer_addrs().await {
+            match self.network.send_sync_message(addr, &message).await {
+                Ok(()) => sent += 1,
+                Err(e) => warn!("Failed to announce {} to {}: {:?}", manifest.cid, addr, e),
+            }
+        }
+        sent
+    }
+
+    /// Pushes a retraction to every connected peer. Returns how many peers it
+    /// was sent to.
+    pub async fn announce_retraction(&self, retraction: &Retraction) -> usize {
+        let message = SyncMessage::AnnounceRetraction { retraction: retraction.clone() };
+        let mut sent = 0;
+        for addr in self.network.peer_addrs().await {
+            match self.network.send_sync_message(addr, &message).await {
+                Ok(()) => sent += 1,
+                Err(e) => warn!("Failed to announce retraction of {} to {}: {:?}", retraction.cid, addr, e),
+            }
+        }
+        sent
+    }
+
+    /// Checks a retraction's signature and applies it. Records for manifests
+    /// not stored yet are kept, up to a cap per peer, so the manifest is
+    /// refused when it arrives.
+    async fn apply_retraction(&self, addr: SocketAddr, retraction: Retraction) -> Result<()> {
+        if let Err(e) = retraction.verify() {
+            warn!("Ignoring retraction from {}: {}", addr, e);
+            return Ok(());
+        }
+        let cid = retraction.cid.clone();
+        let source = addr.to_string();
+        let outcome = self.storage.call(move |store| retraction::apply(store, &retraction, Some(&source))).await?;
+        if let RetractionOutcome::Applied(manifest) = outcome {
+            if let Some(ipfs) = &self.pins {
+                for pinned in retraction::pinned_cids(&manifest, None) {
+                    if let Err(e) = ipfs.unpin(&pinned).await {
+                        warn!("Failed to unpin {}: {}", pinned, e);
+                    }
+                }
+            }
+            self.emit(SyncEvent::ManifestRetracted { peer: addr, cid });
+        }
+        Ok(())
+    }
+
+    /// Fetches manifests referenced by `cid`'s lineage that the index doesn't
+    /// know yet and adds them to it. Returns how many were fetched.
+    /// Each fetched manifest can name further parents, so this repeats until
+    /// the lineage is complete or no peer has what's missing.
+    pub async fn fill_lineage(&self, index: &mut Index, cid: &str) -> Result<usize> {
+        let mut fetched = 0;
+        let mut tried = HashSet::new();
+        loop {
+            let missing: Vec<String> = index
+                .unknown_references(cid)
+                .into_iter()
+                .filter(|c| tried.insert(c.clone()))
+                .collect();
+            if missing.is_empty() {
+                return Ok(fetched);
+            }
+            for missing in missing {
+                if let Some(manifest) = self.fetch_manifest(&missing).await? {
+                    index.insert(manifest);
+                    fetched += 1;
+                }
+            }
+        }
+    }
+
+    async fn store_manifest_data(&self, addr: SocketAddr, cid: &str, data: Vec<u8>) -> Result<bool> {
+        let manifest = Manifest {
+            cid: cid.to_string(),
+            data,
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+        if !manifest.verify_content() {
+            warn!("Manifest data from {} does not hash to {}", addr, cid);
+            self.forget_holder(cid, addr).await;
+            self.emit(SyncEvent::ManifestRejected {
+                peer: addr,
+                cid: cid.to_string(),
+                reason: "content hash mismatch".to_string(),
+            });
+            return Ok(false);
+        }
+        self.verify_and_store(addr, &manifest).await
+    }
+
+    async fn forget_holder(&self, cid: &str, addr: SocketAddr) {
+        if let Some(peers) = self.holders.write().await.get_mut(cid) {
+            peers.remove(&addr);
+        }
+    }
+
+    /// Stores a manifest that arrived whole (catalog chunk or announcement),
+    /// checking its content the same way a fetched manifest is checked.
+    async fn ingest(&self, addr: SocketAddr, manifest: &Manifest) -> Result<bool> {
+        if !manifest.verify_content() {
+            warn!("Manifest {} from {} does not hash to its CID", manifest.cid, addr);
+            self.emit(SyncEvent::ManifestRejected {
+                peer: addr,
+                cid: manifest.cid.clone(),
+                reason: "content hash mismatch".to_string(),
+            });
+            return Ok(false);
+        }
+        self.verify_and_store(addr, manifest).await
+    }
+
+    /// Verifies a manifest's creator signature and anchor, then merges it
+    /// into storage. Returns whether it was stored.
+    async fn verify_and_store(&self, addr: SocketAddr, manifest: &Manifest) -> Result<bool> {
+        // Offline checks first: forgeries never cost an RPC round trip
+        let schemas = self.schemas.clone();
+        let candidate = manifest.clone();
+        let document = match self.storage.call(move |store| ingest::admit(store, &schemas, &candidate)).await? {
+            Admission::Admitted(document) => document,
+            Admission::Tombstoned => {
+                info!("Skipping tombstoned manifest {} from {}", manifest.cid, addr);
+                return Ok(false);
+            }
+            Admission::Retracted => {
+                info!("Skipping retracted manifest {} from {}", manifest.cid, addr);
+                return Ok(false);
+            }
+            Admission::Invalid(reason) => {
+                warn!("Manifest {} from {} is invalid: {}", manifest.cid, addr, reason);
+                self.reject(addr, &manifest.cid, reason).await?;
+                return Ok(false);
+            }
+        };
+        // Not an error: the peer just publishes more than this node
+        // replicates, or predates `RequestCatalog` filters
+        if !self.replicate.matches(Some(&document)) {
+            debug!("Skipping manifest {} from {}: outside the replicate filter", manifest.cid, addr);
+            return Ok(false);
+        }
+        if document.is_newer_schema() {
+            debug!("Manifest {} uses schema v{}; keeping unknown fields as-is", manifest.cid, document.schema_version);
+        }
+        // A fresh cache entry already proves the anchor; otherwise ask
+        // Solana, where anchors are derived from the creator's key
+        let cached = self.provenance.cached(&manifest.cid).await?;
+        let anchored = cached.is_some()
+            || matches!(self.anchor.verify_manifest(&manifest.cid, &document.creator).await, Ok(true));
+        if anchored {
+            let provenance = match cached {
+                Some(provenance) => Some(provenance),
+                None => self.provenance.get(&manifest.cid, &document.creator).await.ok().flatten(),
+            };
+            let anchored_at = provenance.as_ref().and_then(|p| p.anchored_at);
+            let outcome = self.storage.merge_manifest(manifest, &addr.to_string(), anchored_at).await?;
+            if outcome == MergeOutcome::Conflict {
+                self.emit(SyncEvent::ManifestRejected {
+                    peer: addr,
+                    cid: manifest.cid.clone(),
+                    reason: "data conflicts with stored copy".to_string(),
+                });
+                return Ok(false);
+            }
+            // The cache can only hold provenance for stored manifests, so a
+            // new manifest's lookup was a miss that couldn't be cached yet
+            if let (MergeOutcome::Inserted, Some(provenance)) = (outcome, provenance) {
+                self.provenance.put(&manifest.cid, provenance).await?;
+            }
+            self.emit(SyncEvent::ManifestStored { peer: addr, cid: manifest.cid.clone() });
+            let request = SyncMessage::RequestAttestations { cid: manifest.cid.clone() };
+            if let Err(e) = self.network.send_sync_message(addr, &request).await {
+                warn!("Failed to request attestations for {} from {}: {:?}", manifest.cid, addr, e);
+            }
+            Ok(true)
+        } else {
+            warn!("Manifest {} failed verification", manifest.cid);
+            self.reject(addr, &manifest.cid, "anchor verification failed".to_string()).await?;
+            Ok(false)
+        }
+    }
+
+    /// Records why a content-verified manifest was refused and tells
+    /// subscribers. Hash mismatches aren't recorded: the CID may well be
+    /// fine, only that peer's copy is wrong.
+    async fn reject(&self, addr: SocketAddr, cid: &str, reason: String) -> Result<()> {
+        self.storage
+            .record_rejection(&ManifestRejection {
+                cid: cid.to_string(),
+                source: addr.to_string(),
+                reason: reason.clone(),
+                rejected_at: chrono::Utc::now().timestamp(),
+            })
+            .await?;
+        self.emit(SyncEvent::ManifestRejected { peer: addr, cid: cid.to_string(), reason });
+        Ok(())
+    }
+
+    pub async fn handle_sync_message(&self, addr: SocketAddr, message: SyncMessage) -> Result<Option<SyncMessage>> {
         match message {
-            SyncMessage::RequestCatalog { since } => {
-                let manifests = self.storage.list_manifests()?;
-                let filtered: Vec<Manifest> = if let Some(ts) = since {
-                    manifests.into_iter().filter(|m| m.timestamp > ts).collect()
+            SyncMessage::RequestCatalog { since, after, filter } => {
+                let first_page = after.is_none();
+                let mut query = ManifestQuery {
+                    since,
+                    after,
+                    limit: Some(CATALOG_PAGE_SIZE + 1),
+                    ..Default::default()
+                };
+                filter.apply(&mut query);
+                query.namespaces = self.served_namespaces(query.namespaces);
+                // One extra row tells us whether another page follows
+                let mut chunk = self.storage.query_manifests(&query).await?;
+                let has_more = chunk.len() > CATALOG_PAGE_SIZE;
+                chunk.truncate(CATALOG_PAGE_SIZE);
+                let next = chunk.last().map(|m| ManifestCursor {
+                    timestamp: m.timestamp,
+                    cid: m.cid.clone(),
+                });
+                query.after = None;
+                let total = self.storage.count_manifests(&query).await?;
+                // Only signed retractions travel, and only for manifests the
+                // peer could have received from us; local evictions stay local
+                let retractions = if first_page {
+                    self.storage.list_signed_retractions(&ManifestQuery { limit: None, ..query.clone() }).await?
                 } else {
-                    manifests
+                    Vec::new()
                 };
-                // Send in chunks if large
-                let chunk = filtered.into_iter().take(100).collect::<Vec<_>>();
-                let has_more = chunk.len() == 100;
-                Ok(Some(SyncMessage::CatalogChunk { manifests: chunk, has_more }))
+                Ok(Some(SyncMessage::CatalogChunk {
+                    manifests: chunk,
+                    retractions,
+                    has_more,
+                    next: if has_more { next } else { None },
+                    total: Some(total),
+                }))
             }
-            SyncMessage::CatalogChunk { manifests, has_more: _ } => {
+            SyncMessage::CatalogChunk { manifests, retractions, has_more, next, total } => {
+                for retraction in retractions {
+                    self.apply_retraction(addr, retraction).await?;
+                }
+                {
+                    let mut holders = self.holders.write().await;
+                    for manifest in &manifests {
+                        holders.entry(manifest.cid.clone()).or_default().insert(addr);
+                    }
+                }
+                let count = manifests.len() as u64;
+                let mut state = self
+                    .update_progress(addr, |state| {
+                        state.pages_fetched += 1;
+                        state.pending_verification += count;
+                        if total.is_some() {
+                            state.advertised_total = total;
+                        }
+                    })
+                    .await;
+                self.emit(SyncEvent::PageReceived { peer: addr, state: state.clone() });
                 for manifest in manifests {
-                    // Verify with Solana
-                    if let Ok(true) = self.anchor.verify_manifest(&manifest.cid, "mock_creator").await {
-                        if let Ok(Some(provenance)) = self.anchor.get_manifest_provenance(&manifest.cid, "mock_creator").await {
-                            let prov = crate::storage::Provenance {
-                                finalized: provenance.finalized,
-                                attestation_count: provenance.attestation_count,
-                                tx_signature: provenance.tx_signature,
-                                slot: provenance.slot,
-                            };
-                            self.storage.cache_provenance(&manifest.cid, &prov)?;
+                    let result = self.ingest(addr, &manifest).await;
+                    state = self
+                        .update_progress(addr, |state| {
+                            state.pending_verification = state.pending_verification.saturating_sub(1);
+                            state.manifests_received += 1;
+                        })
+                        .await;
+                    if result.is_err() {
+                        self.save_progress(&state).await;
+                    }
+                    result?;
+                }
+                if has_more {
+                    self.save_progress(&state).await;
+                    if let Some(after) = next {
+                        let since = self.progress.read().await.get(&addr).map(|s| s.last_sync);
+                        let request = SyncMessage::RequestCatalog {
+                            since,
+                            after: Some(after),
+                            filter: self.replicate.clone(),
+                        };
+                        if let Err(e) = self.network.send_sync_message(addr, &request).await {
+                            warn!("Failed to request next catalog page from {}: {:?}", addr, e);
+                            self.emit(SyncEvent::Failed { peer: addr, error: e.to_string() });
                         }
-                        self.storage.insert_manifest(&manifest)?;
-                    } else {
-                        warn!("Manifest {} failed verification", manifest.cid);
                     }
+                } else {
+                    let state = self
+                        .update_progress(addr, |state| {
+                            state.complete = true;
+                            state.last_sync = state.started_at;
+                        })
+                        .await;
+                    self.save_progress(&state).await;
+                    info!("Sync with {} complete: {} manifests", addr, state.manifests_received);
+                    let cache = self.provenance.stats();
+                    info!(
+                        "Provenance cache: {} hits, {} misses ({:.0}% hit ratio)",
+                        cache.hits,
+                        cache.misses,
+                        cache.hit_ratio() * 100.0
+                    );
+                    self.emit(SyncEvent::Completed { peer: addr, state });
                 }
                 Ok(None)
             }
             SyncMessage::RequestManifest { cid } => {
-                if let Some(manifest) = self.storage.get_manifest(&cid)? {
+                let manifest = self.storage.get_manifest(&cid).await?;
+                if let Some(manifest) = manifest.filter(|m| self.namespaces.serves(&m.namespace())) {
                     Ok(Some(SyncMessage::ManifestData { cid, data: manifest.data }))
                 } else {
-                    Ok(None)
+                    Ok(Some(SyncMessage::ManifestNotFound { cid }))
                 }
             }
-            SyncMessage::ManifestData { cid: _, data: _ } => {
-                // Store the data if needed
+            SyncMessage::ManifestData { cid, data } => {
+                self.store_manifest_data(addr, &cid, data).await?;
+                Ok(None)
+            }
+            SyncMessage::AnnounceManifest { manifest } => {
+                self.holders.write().await.entry(manifest.cid.clone()).or_default().insert(addr);
+                self.ingest(addr, &manifest).await?;
+                Ok(None)
+            }
+            SyncMessage::AnnounceRetraction { retraction } => {
+                self.apply_retraction(addr, retraction).await?;
+                Ok(None)
+            }
+            SyncMessage::ManifestNotFound { cid } => {
+                self.forget_holder(&cid, addr).await;
+                Ok(None)
+            }
+            SyncMessage::RequestAttestations { cid } => {
+                let attestations = self.storage.get_attestations(&cid).await?;
+                Ok(Some(SyncMessage::Attestations { cid, attestations }))
+            }
+            SyncMessage::Attestations { cid, attestations } => {
+                if self.storage.get_manifest(&cid).await?.is_none() {
+                    warn!("Received attestations for unknown manifest {} from {}", cid, addr);
+                    return Ok(None);
+                }
+                for attestation in attestations {
+                    if attestation.manifest_cid != cid {
+                        warn!("Attestation for {} sent under {} by {}", attestation.manifest_cid, cid, addr);
+                        continue;
+                    }
+                    match anchor::verify_attestation(&attestation) {
+                        Ok(true) => self.storage.insert_attestation(&attestation).await?,
+                        Ok(false) => warn!("Attestation by {} for {} failed signature check", attestation.validator, cid),
+                        Err(e) => warn!("Attestation by {} for {} rejected: {}", attestation.validator, cid, e),
+                    }
+                }
                 Ok(None)
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipfs::compute_cid;
+    use crate::network::NetworkConfig;
+
+    async fn network(static_peers: Vec<String>) -> Result<Arc<Network>> {
+        Network::new(NetworkConfig {
+            dns_seeds: Vec::new(),
+            static_peers,
+            listen_addr: "127.0.0.1:0".to_string(),
+            max_peers: 8,
+            identity_dir: None,
+            insecure_skip_verify: false,
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_request_manifest_over_loopback() -> Result<()> {
+        let data = br#"{"schema_version":1,"creator":"Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS","node_id":"node1","created_at":1}"#.to_vec();
+        let manifest = Manifest { cid: compute_cid(&data), data: data.clone(), timestamp: 1 };
+        let storage = AsyncStorage::in_memory();
+        storage.call({
+            let manifest = manifest.clone();
+            move |store| store.insert_manifest(&manifest)
+        })
+        .await?;
+
+        let server = network(Vec::new()).await?;
+        let server_addr = server.local_addr()?;
+        let engine = SyncEngine::new(
+            storage,
+            server.clone(),
+            Arc::new(Anchor::new("http://127.0.0.1:1", "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS")?),
+            ProvenanceCacheConfig::default(),
+            Arc::new(SchemaRegistry::builtin()),
+            CatalogFilter::default(),
+            NamespaceConfig::default(),
+        )
+        .await;
+        tokio::spawn(async move { server.start().await });
+        tokio::spawn(async move { engine.serve().await });
+
+        let client = network(vec![server_addr.to_string()]).await?;
+        client.connect_bootstrap_peers().await?;
+        assert_eq!(client.wait_for_peers(std::time::Duration::from_secs(5)).await, 1);
+
+        let request = SyncMessage::RequestManifest { cid: manifest.cid.clone() };
+        match client.request_sync_message(server_addr, &request).await? {
+            SyncMessage::ManifestData { cid, data: got } => assert_eq!((cid, got), (manifest.cid.clone(), data)),
+            other => panic!("expected ManifestData, got {:?}", other),
+        }
+        let request = SyncMessage::RequestManifest { cid: "QmMissing".to_string() };
+        assert!(matches!(
+            client.request_sync_message(server_addr, &request).await?,
+            SyncMessage::ManifestNotFound { .. }
+        ));
+        Ok(())
+    }
+}